@@ -3,6 +3,7 @@ use std::io::{Error, Read};
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use futures::Future;
 use image::{Frame, ImageBuffer};
 use smallvec::SmallVec;
@@ -12,16 +13,34 @@ use xdg_desktop::menu::{MenuAssociation, MenuIndex, MenuItem};
 use xdg_desktop::mime_glob::MIMEGlobIndex;
 use gpui::*;
 
+use crate::journal::Journal;
 use crate::models::DirModel;
+use crate::search::SearchMode;
+use crate::sort::{SortMode, SortOrder};
 use crate::views::FileListView;
 
+// A shell command from forg.toml, run on the current or marked items.
+pub struct UserAction {
+    pub name: String,
+    pub command: String,
+    pub key: Option<String>,
+}
+
 pub struct AppGlobal {
-    mime_index: MIMEGlobIndex,
+    mime_index: Arc<MIMEGlobIndex>,
     pub icon_col: IconCollection,
     pub menu_index: MenuIndex,
 
     pub cur_stash: Vec<PathBuf>,
     pub cur_stash_move: bool,
+
+    journal: Journal,
+
+    pub default_sort: SortOrder,
+    pub default_search_mode: SearchMode,
+    // How long to wait on the filesystem before offering to cancel.
+    pub io_timeout: Duration,
+    pub user_actions: Vec<UserAction>,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -104,9 +123,42 @@ impl AppGlobal {
             panic!("Unsupported platform");
         };
 
+        let mut default_sort = SortOrder::default();
+        let mut default_search_mode = SearchMode::Prefix;
+        let mut io_timeout = Duration::from_secs(3);
+        let mut user_actions = vec![];
         if let Ok(config_str) = std::fs::read_to_string(config_path) {
             let config = toml::from_str::<Table>(&config_str).expect("Cannot parse forg.toml!");
             config["icon-theme"].as_str().map(|name| { theme = name.to_string(); });
+            if let Some(mode) = config.get("sort").and_then(|v| v.as_str()) {
+                default_sort.mode = SortMode::parse(mode).expect("Unknown sort in forg.toml!");
+            }
+            if let Some(reverse) = config.get("sort-reverse").and_then(|v| v.as_bool()) {
+                default_sort.reverse = reverse;
+            }
+            if let Some(dirs_first) = config.get("dirs-first").and_then(|v| v.as_bool()) {
+                default_sort.dirs_first = dirs_first;
+            }
+            if let Some(natural) = config.get("natural-sort").and_then(|v| v.as_bool()) {
+                default_sort.natural = natural;
+            }
+            if let Some(mode) = config.get("search-mode").and_then(|v| v.as_str()) {
+                default_search_mode = SearchMode::parse(mode).expect("Unknown search-mode in forg.toml!");
+            }
+            if let Some(secs) = config.get("io-timeout").and_then(|v| v.as_float().or(v.as_integer().map(|i| i as f64))) {
+                io_timeout = Duration::from_secs_f64(secs.max(0.));
+            }
+            for action in config.get("actions").and_then(|v| v.as_array()).into_iter().flatten() {
+                let (Some(name), Some(command)) = (action.get("name").and_then(|v| v.as_str()),
+                                                   action.get("command").and_then(|v| v.as_str())) else {
+                    panic!("Actions in forg.toml need a name and a command!");
+                };
+                user_actions.push(UserAction {
+                    name: name.to_string(),
+                    command: command.to_string(),
+                    key: action.get("key").and_then(|v| v.as_str()).map(|key| key.to_string()),
+                });
+            }
         }
 
         icon_col.scan_with_theme(vec![&theme, "hicolor"], paths);
@@ -133,11 +185,16 @@ impl AppGlobal {
         let cur_stash = vec![];
 
         Self {
-            mime_index,
+            mime_index: Arc::new(mime_index),
             icon_col,
             menu_index,
             cur_stash,
             cur_stash_move: false,
+            journal: Journal::default(),
+            default_sort,
+            default_search_mode,
+            io_timeout,
+            user_actions,
         }
     }
 
@@ -164,8 +221,8 @@ impl AppGlobal {
         })
     }
 
-    pub fn match_mime_type(&self, filename: &str) -> String {
-        self.mime_index.match_filename(filename).unwrap_or("application/x-generic").to_string()
+    pub fn mime_index(&self) -> Arc<MIMEGlobIndex> {
+        self.mime_index.clone()
     }
 
     pub fn match_file_icon(&self, mime: &str, size: usize, scale: f32) -> ImageSource {
@@ -213,6 +270,10 @@ impl AppGlobal {
         std::mem::take(&mut self.cur_stash)
     }
 
+    pub fn journal(&self) -> Journal {
+        self.journal.clone()
+    }
+
     pub fn new_main_window(target: PathBuf, cx: &mut AsyncApp) {
         let bounds = Bounds::new(point(px(0.), px(0.)), size(px(460.), px(480.)));
 
@@ -225,7 +286,11 @@ impl AppGlobal {
                 ..Default::default()
             },
             |window, cx| {
-                let model = cx.new(|_| DirModel::new(target, false));
+                let model = cx.new(|cx| {
+                    let mut model = DirModel::new(target, false, cx);
+                    model.watch(cx);
+                    model
+                });
                 let view = cx.new(|cx| {
                     let mut view = FileListView::new(window, cx, model);
                     view.on_navigate(window, cx);
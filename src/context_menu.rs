@@ -0,0 +1,92 @@
+use gpui::*;
+
+pub enum MenuEntry {
+    Action {
+        label: SharedString,
+        // The key binding, as the user would type it.
+        key: Option<SharedString>,
+        action: Box<dyn Action>,
+    },
+    Separator,
+}
+
+// A popup menu at the pointer. Choosing an entry dispatches its action to
+// target, just like pressing its key would.
+pub struct ContextMenu {
+    position: Point<Pixels>,
+    title: Option<SharedString>,
+    entries: Vec<MenuEntry>,
+    target: FocusHandle,
+}
+
+impl EventEmitter<DismissEvent> for ContextMenu {}
+
+impl ContextMenu {
+    pub fn new(position: Point<Pixels>, title: Option<SharedString>, target: FocusHandle) -> Self {
+        Self {
+            position,
+            title,
+            entries: vec![],
+            target,
+        }
+    }
+
+    pub fn entry(mut self, label: impl Into<SharedString>, action: impl Action, window: &Window) -> Self {
+        let key = window.highest_precedence_binding_for_action(&action).map(|binding| {
+            binding.keystrokes().iter().map(|k| k.unparse()).collect::<Vec<_>>().join(" ").into()
+        });
+        self.entries.push(MenuEntry::Action { label: label.into(), key, action: Box::new(action) });
+        self
+    }
+
+    pub fn separator(mut self) -> Self {
+        if !self.entries.is_empty() {
+            self.entries.push(MenuEntry::Separator);
+        }
+        self
+    }
+
+    fn choose(&mut self, idx: usize, window: &mut Window, cx: &mut Context<Self>) {
+        let MenuEntry::Action { action, .. } = &self.entries[idx] else {
+            return;
+        };
+        let action = action.boxed_clone();
+        self.target.focus(window);
+        cx.emit(DismissEvent);
+        window.dispatch_action(action, cx);
+    }
+}
+
+impl Render for ContextMenu {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let mut menu = div()
+            .occlude()
+            .min_w(px(200.))
+            .py_1()
+            .bg(rgb(0xffffff))
+            .border_1()
+            .border_color(rgb(0x787878))
+            .shadow_md()
+            .text_size(px(12.))
+            .on_mouse_down_out(cx.listener(|_, _, _, cx| cx.emit(DismissEvent)));
+
+        if let Some(title) = &self.title {
+            menu = menu.child(div().px_3().py_1().text_color(rgb(0x787878)).child(title.clone()));
+        }
+        menu = menu.children(self.entries.iter().enumerate().map(|(idx, entry)| match entry {
+            MenuEntry::Action { label, key, .. } => div()
+                .flex()
+                .flex_row()
+                .px_3()
+                .py_1()
+                .cursor_pointer()
+                .hover(|style| style.bg(rgb(0x0068d9)).text_color(rgb(0xf0f0f0)))
+                .on_mouse_up(MouseButton::Left, cx.listener(move |this, _, window, cx| this.choose(idx, window, cx)))
+                .child(div().flex_auto().child(label.clone()))
+                .children(key.clone().map(|key| div().pl_4().child(key))),
+            MenuEntry::Separator => div().my_1().h(px(1.)).bg(rgb(0xdcdcdc)),
+        }));
+
+        deferred(anchored().position(self.position).snap_to_window_with_margin(px(8.)).child(menu))
+    }
+}
@@ -0,0 +1,218 @@
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{DirEntry, FileType, Metadata, Permissions};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use xdg_desktop::mime_glob::MIMEGlobIndex;
+
+use crate::sort;
+use crate::trash;
+
+// Invalid UTF-8 bytes show as \xNN, and backslashes are doubled in every
+// name, so unescape_name() always gives back the same bytes.
+pub fn escape_name(name: &OsStr) -> String {
+    let mut escaped = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        escaped.push_str(&chunk.valid().replace('\\', "\\\\"));
+        for b in chunk.invalid() {
+            escaped.push_str(&format!("\\x{:02X}", b));
+        }
+    }
+    escaped
+}
+
+pub fn unescape_name(name: &str) -> OsString {
+    let bytes = name.as_bytes();
+    let mut raw = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'\\') {
+            raw.push(b'\\');
+            i += 2;
+            continue;
+        }
+        if bytes[i] == b'\\' && bytes.get(i + 1) == Some(&b'x') && i + 4 <= bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 2..i + 4]).ok();
+            if let Some(b) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                raw.push(b);
+                i += 4;
+                continue;
+            }
+        }
+        raw.push(bytes[i]);
+        i += 1;
+    }
+    OsString::from_vec(raw)
+}
+
+// The paths as a text/uri-list, the way file managers hand over files.
+pub fn uri_list(paths: &[PathBuf]) -> String {
+    paths.iter()
+        .map(|path| format!("file://{}\r\n", trash::percent_encode(path.as_os_str())))
+        .collect()
+}
+
+// A directory entry with everything the UI needs, gathered once by the
+// loading worker. Rendering and sorting never touch the filesystem.
+#[derive(Clone)]
+pub struct FileEntry {
+    pub path: PathBuf,
+    pub os_name: OsString,
+    // escape_name(os_name), for display.
+    pub name: String,
+    // Of the entry itself. Symlinks are not followed.
+    pub file_type: FileType,
+    pub symlink_target: Option<PathBuf>,
+    pub size: u64,
+    pub mtime: Option<SystemTime>,
+    pub permissions: Permissions,
+    pub mime: String,
+    // sort::fold_name(os_name), for natural order and matching.
+    pub folded: String,
+}
+
+impl FileEntry {
+    pub fn new(path: PathBuf, mime_index: &MIMEGlobIndex) -> io::Result<Self> {
+        let metadata = path.symlink_metadata()?;
+        Ok(Self::with_metadata(path, metadata, mime_index))
+    }
+
+    pub fn from_dir_entry(ent: &DirEntry, mime_index: &MIMEGlobIndex) -> io::Result<Self> {
+        // Like symlink_metadata(), DirEntry::metadata() does not follow symlinks.
+        let metadata = ent.metadata()?;
+        Ok(Self::with_metadata(ent.path(), metadata, mime_index))
+    }
+
+    fn with_metadata(path: PathBuf, metadata: Metadata, mime_index: &MIMEGlobIndex) -> Self {
+        let os_name = path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+        let name = escape_name(&os_name);
+        let file_type = metadata.file_type();
+        let symlink_target = if file_type.is_symlink() { std::fs::read_link(&path).ok() } else { None };
+        let mime = if file_type.is_dir() {
+            "inode/directory".to_string()
+        } else {
+            mime_index.match_filename(&name).unwrap_or("application/x-generic").to_string()
+        };
+
+        Self {
+            folded: sort::fold_name(&os_name),
+            path,
+            os_name,
+            name,
+            file_type,
+            symlink_target,
+            size: metadata.len(),
+            mtime: metadata.modified().ok(),
+            permissions: metadata.permissions(),
+            mime,
+        }
+    }
+
+    // The entry shows, sorts and matches as name instead of its file name.
+    pub fn named(mut self, name: OsString) -> Self {
+        self.name = escape_name(&name);
+        self.folded = sort::fold_name(&name);
+        self.os_name = name;
+        self
+    }
+
+    // For listings that span directories: the entry shows and sorts as its
+    // path under root.
+    pub fn relative_to(self, root: &Path) -> Self {
+        match self.path.strip_prefix(root).map(|relative| relative.as_os_str().to_os_string()) {
+            Ok(relative) => self.named(relative),
+            Err(_) => self,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type.is_symlink()
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.os_name.as_encoded_bytes().first() == Some(&b'.')
+    }
+}
+
+// Like ls -h: 512B, 4.0K, 12M.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["K", "M", "G", "T", "P", "E"];
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut size = size as f64 / 1024.;
+    let mut unit = 0;
+    while size >= 1024. && unit + 1 < UNITS.len() {
+        size /= 1024.;
+        unit += 1;
+    }
+    if size < 9.95 {
+        format!("{:.1}{}", size, UNITS[unit])
+    } else {
+        format!("{:.0}{}", size, UNITS[unit])
+    }
+}
+
+// Like ls -l: drwxr-xr-x.
+pub fn format_mode(file_type: FileType, permissions: &Permissions) -> String {
+    let mode = permissions.mode();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        '-'
+    };
+    let mut out = String::from(kind);
+    for (shift, special, set, unset) in [(6, 0o4000, 's', 'S'), (3, 0o2000, 's', 'S'), (0, 0o1000, 't', 'T')] {
+        let bits = mode >> shift;
+        out.push(if bits & 4 != 0 { 'r' } else { '-' });
+        out.push(if bits & 2 != 0 { 'w' } else { '-' });
+        out.push(match (bits & 1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => unset,
+            (true, false) => 'x',
+            (false, false) => '-',
+        });
+    }
+    out
+}
+
+// In local time, like 2024-05-01 13:45.
+pub fn format_mtime(time: SystemTime) -> String {
+    let secs = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as libc::time_t,
+        Err(e) => -(e.duration().as_secs() as libc::time_t),
+    };
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min)
+}
+
+// Free and total bytes of the filesystem holding path. Free is what an
+// unprivileged user can still write, like df shows.
+pub fn fs_space(path: &Path) -> io::Result<(u64, u64)> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let frsize = stat.f_frsize as u64;
+    Ok((stat.f_bavail as u64 * frsize, stat.f_blocks as u64 * frsize))
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::fs::{DirEntry, File, ReadDir};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use memmap::Mmap;
+use regex::{bytes, Regex};
+use smol::channel::Sender;
+
+use crate::entry::{self, FileEntry};
+use crate::models::{Batch, ListOptions, LOAD_BATCH_TIME};
+use crate::search;
+use crate::sort;
+
+// Files bigger than this are mapped instead of read.
+const MMAP_MIN_SIZE: u64 = 64 << 10;
+// Files with a NUL byte this early on are binaries.
+const BINARY_CHECK_SIZE: usize = 8 << 10;
+// How much of a file is scanned between checks for whether to stop.
+const CANCEL_CHECK_SIZE: usize = 4 << 20;
+const MAX_PREVIEWS: usize = 3;
+const MAX_PREVIEW_LEN: usize = 80;
+
+// A listing of what a find or grep matched under root. Their paths are all
+// it takes to read it again.
+#[derive(Clone)]
+pub struct FindListing {
+    pub root: PathBuf,
+    // What was searched for, like "find *.rs".
+    pub pattern: String,
+    pub paths: Vec<PathBuf>,
+    // Matching lines, by path, for grep.
+    pub previews: HashMap<PathBuf, Vec<String>>,
+}
+
+// Everything below root, depth first. Does not follow symlinks. Unreadable
+// directories are skipped, like find does after complaining.
+pub struct Walk {
+    dirs: Vec<PathBuf>,
+    entries: Option<ReadDir>,
+    show_hidden: bool,
+}
+
+impl Walk {
+    pub fn new(root: PathBuf, show_hidden: bool) -> Self {
+        Self { dirs: vec![root], entries: None, show_hidden }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        loop {
+            if let Some(entries) = self.entries.as_mut() {
+                match entries.next() {
+                    Some(Ok(entry)) => {
+                        if !self.show_hidden && entry.file_name().as_encoded_bytes().first() == Some(&b'.') {
+                            continue;
+                        }
+                        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                            self.dirs.push(entry.path());
+                        }
+                        return Some(entry);
+                    }
+                    Some(Err(_)) => continue,
+                    None => self.entries = None,
+                }
+            }
+            let dir = self.dirs.pop()?;
+            self.entries = std::fs::read_dir(dir).ok();
+        }
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '[' => {
+                re.push('[');
+                if chars.next_if(|c| *c == '!' || *c == '^').is_some() {
+                    re.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        re.push('\\');
+                    }
+                    re.push(c);
+                }
+                re.push(']');
+            }
+            c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    re.push('$');
+    re
+}
+
+// Globs match the whole file name, like find -name. Regexes match anywhere
+// in it.
+pub fn name_matcher(pattern: &str, is_regex: bool) -> Result<Regex, String> {
+    let re = if is_regex { pattern.to_string() } else { glob_to_regex(pattern) };
+    Regex::new(&re).map_err(|err| search::regex_error(&err))
+}
+
+// Walks root, sending the entries for which matches gives lines to show,
+// maybe none, in batches. Stops early once nobody is listening.
+fn walk_matching(root: PathBuf, options: ListOptions, send: Sender<Batch>,
+                 mut matches: impl FnMut(&DirEntry) -> Option<Vec<String>>) {
+    let mut batch = Batch::default();
+    let mut start = Instant::now();
+    for entry in Walk::new(root.clone(), options.show_hidden) {
+        if send.is_closed() {
+            return;
+        }
+        if let Some(lines) = matches(&entry) {
+            if let Ok(ent) = FileEntry::from_dir_entry(&entry, &options.mime_index) {
+                if !lines.is_empty() {
+                    batch.previews.insert(entry.path(), lines);
+                }
+                batch.entries.push(ent.relative_to(&root));
+            }
+        }
+        if start.elapsed() > LOAD_BATCH_TIME {
+            sort::sort_entries(&mut batch.entries, &options.sort);
+            if send.send_blocking(std::mem::take(&mut batch)).is_err() {
+                return;
+            }
+            start = Instant::now();
+        }
+    }
+    sort::sort_entries(&mut batch.entries, &options.sort);
+    let _ = send.send_blocking(Batch { done: true, ..batch });
+}
+
+// Sends the entries under root whose names match.
+pub fn walk(root: PathBuf, re: Regex, options: ListOptions, send: Sender<Batch>) {
+    walk_matching(root, options, send, |entry| {
+        re.is_match(&entry::escape_name(&entry.file_name())).then(Vec::new)
+    });
+}
+
+// Sends the text files under root with lines matching re, and those lines.
+pub fn grep(root: PathBuf, re: bytes::Regex, options: ListOptions, send: Sender<Batch>) {
+    let listener = send.clone();
+    walk_matching(root, options, send, |entry| {
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_file()) {
+            return None;
+        }
+        grep_file(&entry.path(), &re, &|| listener.is_closed())
+    });
+}
+
+// Scans whole lines, about CANCEL_CHECK_SIZE at a time, so a huge file can
+// be given up on halfway. A match across two of those runs is missed.
+fn grep_data(data: &[u8], re: &bytes::Regex, cancelled: &dyn Fn() -> bool) -> Option<Vec<String>> {
+    if data[..data.len().min(BINARY_CHECK_SIZE)].contains(&0) {
+        return None;
+    }
+    let mut found = false;
+    let mut previews = vec![];
+    let mut line_no = 0;
+    let mut rest = data;
+    while !rest.is_empty() && previews.len() < MAX_PREVIEWS {
+        if cancelled() {
+            return None;
+        }
+        let end = match rest.get(CANCEL_CHECK_SIZE..).and_then(|tail| tail.iter().position(|b| *b == b'\n')) {
+            Some(pos) => CANCEL_CHECK_SIZE + pos + 1,
+            None => rest.len(),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        if re.is_match(chunk) {
+            found = true;
+            let lines = chunk.strip_suffix(b"\n").unwrap_or(chunk).split(|b| *b == b'\n').enumerate()
+                .filter(|(_, line)| re.is_match(line))
+                .take(MAX_PREVIEWS - previews.len())
+                .map(|(idx, line)| {
+                    let line = String::from_utf8_lossy(line);
+                    let line: String = line.trim().chars().take(MAX_PREVIEW_LEN).collect();
+                    format!("{}: {}", line_no + idx + 1, line)
+                });
+            previews.extend(lines);
+        }
+        line_no += chunk.iter().filter(|b| **b == b'\n').count();
+        rest = tail;
+    }
+    // A match across lines has no line to show.
+    found.then_some(previews)
+}
+
+// The lines of a text file matching re, or None if nothing matches or
+// cancelled() says to stop. Binaries never match.
+pub fn grep_file(path: &Path, re: &bytes::Regex, cancelled: &dyn Fn() -> bool) -> Option<Vec<String>> {
+    let mut file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    if size >= MMAP_MIN_SIZE {
+        // Like grep, this trusts the file not to shrink while mapped.
+        let data = unsafe { Mmap::map(&file) }.ok()?;
+        grep_data(&data, re, cancelled)
+    } else {
+        let mut data = Vec::with_capacity(size as usize);
+        file.read_to_end(&mut data).ok()?;
+        grep_data(&data, re, cancelled)
+    }
+}
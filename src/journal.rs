@@ -0,0 +1,92 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OpKind {
+    Rename,
+    Move,
+    Copy,
+    Link,
+    Trash,
+}
+
+impl OpKind {
+    fn to_str(self) -> &'static str {
+        match self {
+            Self::Rename => "rename",
+            Self::Move => "move",
+            Self::Copy => "copy",
+            Self::Link => "link",
+            Self::Trash => "trash",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct FileStamp {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: Option<SystemTime>,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        Some(Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.modified().ok(),
+        })
+    }
+}
+
+// A completed filesystem mutation. dst is where the item is now.
+#[derive(Clone)]
+pub struct JournalEntry {
+    pub kind: OpKind,
+    pub src: PathBuf,
+    pub dst: PathBuf,
+    stamp: Option<FileStamp>,
+}
+
+impl JournalEntry {
+    pub fn new(kind: OpKind, src: PathBuf, dst: PathBuf) -> Self {
+        let stamp = FileStamp::of(&dst);
+        Self { kind, src, dst, stamp }
+    }
+
+    pub fn describe(&self) -> String {
+        format!("{} {} -> {}", self.kind.to_str(), self.src.display(), self.dst.display())
+    }
+
+    // Makes sure replaying the inverse will not clobber anything.
+    pub fn check(&self) -> Result<(), String> {
+        if self.stamp.is_none() || FileStamp::of(&self.dst) != self.stamp {
+            return Err(format!("{} has changed since", self.dst.display()));
+        }
+        // Copies and links leave their source in place.
+        if !matches!(self.kind, OpKind::Copy | OpKind::Link) && self.src.symlink_metadata().is_ok() {
+            return Err(format!("{} exists again", self.src.display()));
+        }
+        Ok(())
+    }
+}
+
+// The undo journal. Workers hold a handle and record each operation as
+// soon as it is done, whatever happens to the re-read after it.
+#[derive(Clone, Default)]
+pub struct Journal(Arc<Mutex<Vec<JournalEntry>>>);
+
+impl Journal {
+    pub fn record(&self, ops: Vec<JournalEntry>) {
+        self.0.lock().unwrap().extend(ops);
+    }
+
+    pub fn pop(&self) -> Option<JournalEntry> {
+        self.0.lock().unwrap().pop()
+    }
+}
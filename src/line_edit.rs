@@ -45,9 +45,12 @@ pub struct LineEdit {
 }
 
 pub struct CommitEvent;
+// The content was edited by the user.
+pub struct ChangeEvent;
 
 impl EventEmitter<DismissEvent> for LineEdit {}
 impl EventEmitter<CommitEvent> for LineEdit {}
+impl EventEmitter<ChangeEvent> for LineEdit {}
 
 impl LineEdit {
     pub fn new(window: &mut Window, cx: &mut Context<Self>) -> Self {
@@ -293,6 +296,16 @@ impl LineEdit {
         }).unwrap_or(limit)
     }
 
+    // Replaces the content without emitting ChangeEvent.
+    pub fn set_text(&mut self, text: String, cx: &mut Context<Self>) {
+        self.content = text.into();
+        self.selected_range = self.content.len()..self.content.len();
+        self.selection_reversed = false;
+        self.marked_range = None;
+        self.is_selecting = false;
+        cx.notify();
+    }
+
     pub fn reset(&mut self) {
         self.content = "".into();
         self.selected_range = 0..0;
@@ -365,6 +378,7 @@ impl EntityInputHandler for LineEdit {
                 .into();
         self.selected_range = range.start + new_text.len()..range.start + new_text.len();
         self.marked_range.take();
+        cx.emit(ChangeEvent);
         cx.notify();
     }
 
@@ -392,6 +406,7 @@ impl EntityInputHandler for LineEdit {
             .map(|new_range| new_range.start + range.start..new_range.end + range.end)
             .unwrap_or_else(|| range.start + new_text.len()..range.start + new_text.len());
 
+        cx.emit(ChangeEvent);
         cx.notify();
     }
 
@@ -6,9 +6,17 @@ use std::{ffi::OsString, io, os::unix::ffi::{OsStrExt, OsStringExt}, path::PathB
 
 pub mod line_edit;
 pub mod dialog;
+pub mod context_menu;
 pub mod models;
 pub mod views;
 pub mod app_global;
+pub mod trash;
+pub mod journal;
+pub mod watcher;
+pub mod sort;
+pub mod search;
+pub mod find;
+pub mod entry;
 
 async fn handle_client(cx: &mut AsyncApp, stream: &mut UnixStream) -> io::Result<()> {
     let mut szbuf = [0u8; 2];
@@ -23,11 +31,7 @@ async fn handle_client(cx: &mut AsyncApp, stream: &mut UnixStream) -> io::Result
 }
 
 fn main() {
-    let target = PathBuf::from(std::env::args().nth(1).unwrap_or(std::env::var("HOME").unwrap()));
-    if !target.exists() || !target.metadata().is_ok_and(|m| m.is_dir()) {
-        eprintln!("{} is not a dir", target.display());
-        exit(-1);
-    }
+    let target = std::env::args_os().nth(1).or(std::env::var_os("HOME")).map(PathBuf::from).unwrap_or("/".into());
 
     let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or("/tmp".to_string());
     let sock_path = runtime_dir + "/forg.sock";
@@ -1,31 +1,104 @@
 use smol::channel::{Receiver, RecvError, Sender};
 use smol::prelude::*;
-use gpui::{Action, BackgroundExecutor, Context, SharedString, Task};
-use smol::process::Command;
-use std::cmp;
-use std::collections::BTreeSet;
+use gpui::{Action, BackgroundExecutor, Context, EventEmitter, SharedString, Task};
+use smol::process::{Command, Stdio};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ffi::{OsStr, OsString};
-use std::fs::{DirEntry};
+use std::fs::ReadDir;
+use std::os::unix::fs::PermissionsExt;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use std::time::{Duration, SystemTime};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use xdg_desktop::mime_glob::MIMEGlobIndex;
 
 use crate::app_global::AppGlobal;
+use crate::entry::{self, FileEntry};
+use crate::find::{self, FindListing};
+use crate::journal::{JournalEntry, OpKind};
+use crate::search::{self, Matcher, SearchMode};
+use crate::sort::{self, SortOrder};
+use crate::trash::{self, TrashInfo, TRASH_LOCATION};
+use crate::watcher::{DirEvent, DirWatcher};
+
+pub const LOAD_BATCH_TIME: Duration = Duration::from_millis(50);
+// The free space is asked for at most this often.
+const FS_SPACE_INTERVAL: Duration = Duration::from_secs(1);
+
+// What a thread reading a directory or walking a tree sends at a time.
+#[derive(Default)]
+pub struct Batch {
+    pub entries: Vec<FileEntry>,
+    // Matching lines, by path, for grep.
+    pub previews: HashMap<PathBuf, Vec<String>>,
+    // Nothing comes after this one.
+    pub done: bool,
+}
+
+// What dropping items does: copy by default, move with shift, symlink with
+// ctrl.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DropAction {
+    Copy,
+    Move,
+    Link,
+}
 
 pub struct DirHistoryItem {
     current: Option<OsString>,
     path: PathBuf,
+    find: Option<FindListing>,
 }
 
 pub struct DirModel {
     pub dir_path: PathBuf,
-    pub entries: Vec<DirEntry>,
+    pub entries: Vec<FileEntry>,
+    // Both index entries. current is always one of visible.
     pub current: Option<usize>,
     pub marked: BTreeSet<usize>,
+    // Flagged for deletion by x, apart from the marks.
+    pub flagged: BTreeSet<usize>,
+    // Indices of the entries that pass the filter, in order.
+    pub visible: Vec<usize>,
+    pub filter: String,
+    filter_matcher: Option<Matcher>,
     pub history: Vec<DirHistoryItem>,
     pub start_with: String,
+    // Where the cursor was when the search started, and the query and
+    // cursor before each isearch step, for backspace to go back to.
+    search_origin: Option<OsString>,
+    search_stack: Vec<(String, Option<OsString>)>,
+    pub search_mode: SearchMode,
+    // start_with compiled for search_mode, or why it would not compile.
+    matcher: Result<Matcher, String>,
     pub show_hidden: bool,
+    pub sort: SortOrder,
+    // Parallel to entries when browsing the trash, empty otherwise.
+    pub trash: Vec<TrashInfo>,
+    // What was searched for when listing what find_name() or find_grep()
+    // found under dir_path.
+    pub find: Option<String>,
+    // Matching lines of the files find_grep() found.
+    previews: HashMap<PathBuf, Vec<String>>,
+    // What watch_task watches. The watcher itself lives in the task.
+    watching: Option<PathBuf>,
+    watch_task: Option<Task<()>>,
+    // Free and total bytes of the filesystem holding dir_path, from a
+    // statvfs() done off the UI thread.
+    pub fs_space: Option<(u64, u64)>,
+    fs_space_task: Option<Task<()>>,
+    // Something changed while fs_space_task was busy.
+    fs_space_stale: bool,
+    // Big directories are read in batches after they are opened.
+    pub loading: bool,
+    load_task: Option<Task<()>>,
+    // Where the cursor should go once that entry has been streamed in.
+    pending_focus: Option<OsString>,
+    // Names the watcher reported while loading. Later batches may have
+    // read them before the change, so they are left out.
+    changed_while_loading: HashSet<OsString>,
 }
 
 pub struct DialogAction {
@@ -174,10 +247,148 @@ pub async fn worker_should_exit(input_recv: &Receiver<DialogResponse>) -> bool {
     }
 }
 
+// Runs fun on a thread of its own, so a call stuck on a dead network mount
+// ties up only that thread. None if fun panicked.
+pub async fn blocking<T: Send + 'static>(fun: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    let (send, recv) = smol::channel::bounded(1);
+    std::thread::spawn(move || {
+        let _ = send.send_blocking(fun());
+    });
+    recv.recv().await.ok()
+}
+
+// blocking() for filesystem calls, with a panic as an error.
+pub async fn blocking_io<T: Send + 'static>(fun: impl FnOnce() -> std::io::Result<T> + Send + 'static) -> std::io::Result<T> {
+    blocking(fun).await.unwrap_or_else(|| Err(std::io::Error::other("the call panicked")))
+}
+
+// Like blocking(), but once timeout passes, asks the user whether to keep
+// waiting. Cancelling leaves the stuck thread behind.
+pub async fn worker_blocking<T: Send + 'static>(what: String, timeout: Duration,
+                                                fun: impl FnOnce() -> Result<T, String> + Send + 'static,
+                                                ui_send: &Sender<DialogRequest>,
+                                                input_recv: &Receiver<DialogResponse>) -> Result<T, String> {
+    let mut call = Box::pin(blocking(fun));
+    let timed_out = async {
+        smol::Timer::after(timeout).await;
+        None
+    };
+    let output = match async { Some((&mut call).await) }.or(timed_out).await {
+        Some(output) => output,
+        None => {
+            while !input_recv.is_empty() {
+                let _ = input_recv.recv().await;
+            }
+            let _ = ui_send.send(DialogRequest::new(format!("Still waiting for {}...", what).into(),
+                                                    vec![DialogAction::new("Cancel", "ctrl-g")])).await;
+            let cancelled = async {
+                let _ = input_recv.recv().await;
+                None
+            };
+            async { Some(call.await) }.or(cancelled).await.ok_or(format!("Gave up waiting for {}", what))?
+        }
+    };
+    output.unwrap_or(Err(format!("Failed while accessing {}", what)))
+}
+
+// Splits an expanded Exec line into arguments, unquoting them the way the
+// shell would, and puts the raw path wherever the placeholder ended up. A
+// placeholder expanded from %u or %U is a file:// URI.
+#[cfg(target_os = "linux")]
+fn exec_args(cmd: &str, placeholder: &str, path: &Path) -> Vec<OsString> {
+    use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => words.extend(word.take()),
+            '\'' => {
+                let w = word.get_or_insert_with(String::new);
+                w.extend(chars.by_ref().take_while(|c| *c != '\''));
+            },
+            '"' => {
+                let w = word.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some(c) if "\"`$\\".contains(c) => w.push(c),
+                            Some(c) => { w.push('\\'); w.push(c); },
+                            None => w.push('\\'),
+                        },
+                        c => w.push(c),
+                    }
+                }
+            },
+            '\\' => word.get_or_insert_with(String::new).extend(chars.next()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    let uri = format!("file://{}", placeholder);
+    words.into_iter().map(|w| {
+        let w = w.replace(&uri, &format!("file://{}", trash::percent_encode(path.as_os_str())));
+        let mut raw = Vec::new();
+        for (i, part) in w.split(placeholder).enumerate() {
+            if i > 0 {
+                raw.extend_from_slice(path.as_os_str().as_bytes());
+            }
+            raw.extend_from_slice(part.as_bytes());
+        }
+        OsString::from_vec(raw)
+    }).collect()
+}
+
+// Why a directory could not be listed. Only the IOWorker and the dialog
+// turn it into text.
+#[derive(Debug)]
+pub enum LoadError {
+    ReadDir(PathBuf, std::io::Error),
+    // The directory shown was deleted or moved away.
+    Gone(PathBuf),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::ReadDir(path, err) => write!(f, "Cannot read {}. {}", path.display(), err),
+            LoadError::Gone(path) => write!(f, "{} was deleted or moved away", path.display()),
+        }
+    }
+}
+
+// The directory a window was opened on could not be read, or the one
+// shown went away.
+pub struct LoadFailedEvent(pub LoadError);
+
+impl EventEmitter<LoadFailedEvent> for DirModel {}
+
 pub struct OpenDirResult {
     path: PathBuf,
-    entries: Vec<DirEntry>,
+    entries: Vec<FileEntry>,
     current: Option<OsString>,
+    trash: Vec<TrashInfo>,
+    // Set when only the first batch has been read.
+    rest: Option<ReadDir>,
+    find: Option<String>,
+    previews: HashMap<PathBuf, Vec<String>>,
+}
+
+#[derive(Clone)]
+pub struct ListOptions {
+    pub show_hidden: bool,
+    pub sort: SortOrder,
+    pub mime_index: Arc<MIMEGlobIndex>,
+    // Read this listing instead when loading its root.
+    pub find: Option<FindListing>,
+}
+
+pub struct RefreshStats {
+    pub appeared: usize,
+    pub disappeared: usize,
 }
 
 impl DirModel {
@@ -200,89 +411,867 @@ impl DirModel {
         })
     }
 
-    fn load_entries(path: &Path, show_hidden: bool) -> Vec<DirEntry> {
-        let mut entries = std::fs::read_dir(path)
-            .unwrap()
+    fn load_entries(path: &Path, options: &ListOptions) -> std::io::Result<Vec<FileEntry>> {
+        let mut entries = std::fs::read_dir(path)?
             .filter_map(|entry| entry.ok())
-            .filter(|entry| show_hidden || entry.file_name().as_encoded_bytes()[0] != b'.')
-            .collect::<Vec<DirEntry>>();
-        entries.sort_by(|p, q| {
-            if let (Ok(pf), Ok(qf)) = (p.file_type(), q.file_type()) {
-                if pf.is_dir() && !qf.is_dir() {
-                    return cmp::Ordering::Less;
-                } else if !pf.is_dir() && qf.is_dir() {
-                    return cmp::Ordering::Greater;
+            .filter_map(|entry| FileEntry::from_dir_entry(&entry, &options.mime_index).ok())
+            .filter(|entry| options.show_hidden || !entry.is_hidden())
+            .collect::<Vec<FileEntry>>();
+        sort::sort_entries(&mut entries, &options.sort);
+
+        Ok(entries)
+    }
+
+    pub fn list_options(&self, cx: &Context<Self>) -> ListOptions {
+        ListOptions {
+            show_hidden: self.show_hidden,
+            sort: self.sort,
+            mime_index: cx.global::<AppGlobal>().mime_index(),
+            find: self.find_listing(),
+        }
+    }
+
+    fn find_listing(&self) -> Option<FindListing> {
+        self.find.as_ref().map(|pattern| FindListing {
+            root: self.dir_path.clone(),
+            pattern: pattern.clone(),
+            paths: self.entries.iter().map(|ent| ent.path.clone()).collect(),
+            previews: self.previews.clone(),
+        })
+    }
+
+    // Reads the found entries again, dropping those that are gone.
+    fn load_find_entries(find: &FindListing, options: &ListOptions) -> Vec<FileEntry> {
+        let mut entries: Vec<_> = find.paths.iter()
+            .filter_map(|path| FileEntry::new(path.clone(), &options.mime_index).ok())
+            .map(|ent| ent.relative_to(&find.root))
+            .filter(|ent| options.show_hidden || !ent.is_hidden())
+            .collect();
+        sort::sort_entries(&mut entries, &options.sort);
+        entries
+    }
+
+    fn load_trash_entries(mime_index: &MIMEGlobIndex) -> (Vec<FileEntry>, Vec<TrashInfo>) {
+        let mut entries = vec![];
+        let mut infos = vec![];
+        for info in trash::list_trash() {
+            // Skip .trashinfo records whose file is gone.
+            let Ok(ent) = FileEntry::new(info.file_path.clone(), mime_index) else {
+                continue;
+            };
+            // Under files/ it may have been renamed to foo.2. Searching and
+            // filtering go by the name it had, which is what is shown.
+            let original = info.original.file_name().unwrap_or_default().to_os_string();
+            entries.push(ent.named(original));
+            infos.push(info);
+        }
+        (entries, infos)
+    }
+
+    fn load_dir_result(path: PathBuf, options: &ListOptions, current: Option<OsString>) -> Result<OpenDirResult, LoadError> {
+        if path.as_os_str() == TRASH_LOCATION {
+            let (entries, trash) = Self::load_trash_entries(&options.mime_index);
+            return Ok(OpenDirResult {
+                path,
+                entries,
+                current,
+                trash,
+                rest: None,
+                find: None,
+                previews: HashMap::new(),
+            });
+        }
+        if let Some(find) = options.find.as_ref().filter(|find| find.root == path) {
+            return Ok(OpenDirResult {
+                entries: Self::load_find_entries(find, options),
+                path,
+                current,
+                trash: vec![],
+                rest: None,
+                find: Some(find.pattern.clone()),
+                previews: find.previews.clone(),
+            });
+        }
+        let entries = Self::load_entries(&path, options).map_err(|err| LoadError::ReadDir(path.clone(), err))?;
+        Ok(OpenDirResult {
+            path,
+            entries,
+            current,
+            trash: vec![],
+            rest: None,
+            find: None,
+            previews: HashMap::new(),
+        })
+    }
+
+    // Re-reads the directory after an operation, asking whether to keep
+    // waiting when it hangs. The timeout is per batch, so a big directory
+    // that is still being read does not count as hanging.
+    async fn reload(path: PathBuf, options: ListOptions, current: Option<OsString>, timeout: Duration,
+                    ui_send: &Sender<DialogRequest>, input_recv: &Receiver<DialogResponse>) -> Result<OpenDirResult, String> {
+        let what = entry::escape_name(path.as_os_str());
+        let mut result = worker_blocking(what.clone(), timeout, {
+            let options = options.clone();
+            move || Self::load_dir_result_partial(path, &options, current).map_err(|err| err.to_string())
+        }, ui_send, input_recv).await?;
+        if result.rest.is_none() {
+            return Ok(result);
+        }
+        while let Some(mut rest) = result.rest.take() {
+            let options = options.clone();
+            let (batch, rest) = worker_blocking(what.clone(), timeout, move || {
+                let (batch, done) = Self::read_batch(&mut rest, &options);
+                Ok((batch, (!done).then_some(rest)))
+            }, ui_send, input_recv).await?;
+            result.entries.extend(batch);
+            result.rest = rest;
+        }
+        sort::sort_entries(&mut result.entries, &options.sort);
+        Ok(result)
+    }
+
+    // Like load_dir_result, but only reads the first batch. The rest is
+    // streamed in after the result is applied.
+    fn load_dir_result_partial(path: PathBuf, options: &ListOptions, current: Option<OsString>) -> Result<OpenDirResult, LoadError> {
+        if path.as_os_str() == TRASH_LOCATION || options.find.as_ref().is_some_and(|find| find.root == path) {
+            return Self::load_dir_result(path, options, current);
+        }
+        let mut rest = std::fs::read_dir(&path).map_err(|err| LoadError::ReadDir(path.clone(), err))?;
+        let (entries, done) = Self::read_batch(&mut rest, options);
+        Ok(OpenDirResult {
+            path,
+            entries,
+            current,
+            trash: vec![],
+            rest: if done { None } else { Some(rest) },
+            find: None,
+            previews: HashMap::new(),
+        })
+    }
+
+    // Reads for up to LOAD_BATCH_TIME. Returns the sorted batch and whether
+    // the whole directory has been read.
+    fn read_batch(rest: &mut ReadDir, options: &ListOptions) -> (Vec<FileEntry>, bool) {
+        let start = Instant::now();
+        let mut batch = vec![];
+        let mut done = true;
+        for entry in rest.by_ref() {
+            if let Some(ent) = entry.ok().and_then(|entry| FileEntry::from_dir_entry(&entry, &options.mime_index).ok()) {
+                if options.show_hidden || !ent.is_hidden() {
+                    batch.push(ent);
+                }
+            }
+            if start.elapsed() > LOAD_BATCH_TIME {
+                done = false;
+                break;
+            }
+        }
+        sort::sort_entries(&mut batch, &options.sort);
+        (batch, done)
+    }
+
+    // Reads the rest of the directory on a thread of its own. Dropping
+    // load_task makes the thread quit after its current batch.
+    fn stream_entries(&mut self, mut rest: ReadDir, cx: &mut Context<Self>) {
+        let options = self.list_options(cx);
+        let (send, recv) = smol::channel::bounded(1);
+        std::thread::spawn(move || {
+            loop {
+                let (entries, done) = Self::read_batch(&mut rest, &options);
+                if send.send_blocking(Batch { entries, done, ..Default::default() }).is_err() || done {
+                    break;
                 }
             }
-            p.file_name().cmp(&q.file_name())
         });
+        self.stream_batches(recv, cx);
+    }
 
-        entries
+    // Merges batches of entries as they arrive, until one says it is the
+    // last.
+    fn stream_batches(&mut self, recv: Receiver<Batch>, cx: &mut Context<Self>) {
+        self.loading = true;
+        self.changed_while_loading.clear();
+        self.load_task = Some(cx.spawn(async move |this, cx| {
+            while let Ok(batch) = recv.recv().await {
+                let done = batch.done;
+                let updated = this.update(cx, |model, cx| {
+                    model.previews.extend(batch.previews);
+                    model.merge_entries(batch.entries);
+                    if done {
+                        model.loading = false;
+                        model.pending_focus = None;
+                        model.changed_while_loading.clear();
+                    }
+                    cx.notify();
+                });
+                if done || updated.is_err() {
+                    break;
+                }
+            }
+        }));
     }
 
-    pub fn new(dir_path: PathBuf, show_hidden: bool) -> Self {
-        Self {
-            entries: Self::load_entries(&dir_path, show_hidden),
+    // Returns whether a directory was still being read.
+    pub fn stop_loading(&mut self, _: &mut Context<Self>) -> bool {
+        let was_loading = self.loading;
+        self.loading = false;
+        self.load_task = None;
+        self.pending_focus = None;
+        self.changed_while_loading.clear();
+        was_loading
+    }
+
+    // Merges a sorted batch into the sorted entries in one pass, moving the
+    // cursor, marks, flags and visible entries along with them.
+    fn merge_entries(&mut self, mut batch: Vec<FileEntry>) {
+        if !self.changed_while_loading.is_empty() {
+            batch.retain(|ent| !self.changed_while_loading.contains(&ent.os_name));
+        }
+        if !batch.is_empty() {
+            self.merge_sorted(batch, &HashSet::new());
+        }
+        if let Some(name) = self.pending_focus.clone() {
+            if self.focus_file_name(&name) {
+                self.pending_focus = None;
+                self.clamp_current();
+            }
+        }
+    }
+
+    // Also drops the old entries named in removed. The cursor, marks and
+    // flags on those go to the batch entry of the same name, if there is
+    // one. Only the batch is run through the filter.
+    fn merge_sorted(&mut self, batch: Vec<FileEntry>, removed: &HashSet<OsString>) {
+        let removed_names = |selected: &BTreeSet<usize>| -> HashSet<OsString> {
+            selected.iter().map(|idx| &self.entries[*idx].os_name).filter(|name| removed.contains(*name)).cloned().collect()
+        };
+        let (marked_names, flagged_names) = (removed_names(&self.marked), removed_names(&self.flagged));
+        let current_name = self.current.map(|idx| &self.entries[idx].os_name).filter(|name| removed.contains(*name)).cloned();
+        let current_back = current_name.as_ref().is_some_and(|name| batch.iter().any(|ent| ent.os_name == *name));
+
+        let capacity = self.entries.len() + batch.len();
+        let old = std::mem::replace(&mut self.entries, Vec::with_capacity(capacity));
+        let mut marked = std::mem::take(&mut self.marked).into_iter().peekable();
+        let mut flagged = std::mem::take(&mut self.flagged).into_iter().peekable();
+        let mut visible = std::mem::take(&mut self.visible).into_iter().peekable();
+        let (mut new_marked, mut new_flagged) = (vec![], vec![]);
+        let mut new_visible = Vec::with_capacity(visible.len() + batch.len());
+        let current = self.current.take();
+        // The current entry was dropped and nothing replaced it yet.
+        let mut current_lost = false;
+
+        let mut old = old.into_iter().enumerate().peekable();
+        let mut batch = batch.into_iter().peekable();
+        loop {
+            // Ties keep the old entry first, like a stable sort would.
+            let from_old = match (old.peek(), batch.peek()) {
+                (Some((_, p)), Some(q)) => sort::compare(p, q, &self.sort) != Ordering::Greater,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            let idx = self.entries.len();
+            let (ent, is_visible) = if from_old {
+                let (old_idx, ent) = old.next().unwrap();
+                let is_marked = marked.next_if_eq(&old_idx).is_some();
+                let is_flagged = flagged.next_if_eq(&old_idx).is_some();
+                let is_visible = visible.next_if_eq(&old_idx).is_some();
+                if !removed.is_empty() && removed.contains(&ent.os_name) {
+                    if current == Some(old_idx) && !current_back {
+                        current_lost = true;
+                    }
+                    continue;
+                }
+                if is_marked {
+                    new_marked.push(idx);
+                }
+                if is_flagged {
+                    new_flagged.push(idx);
+                }
+                if current == Some(old_idx) {
+                    self.current = Some(idx);
+                }
+                (ent, is_visible)
+            } else {
+                let ent = batch.next().unwrap();
+                if marked_names.contains(&ent.os_name) {
+                    new_marked.push(idx);
+                }
+                if flagged_names.contains(&ent.os_name) {
+                    new_flagged.push(idx);
+                }
+                let is_visible = self.filter_matcher.as_ref().is_none_or(|matcher| matcher.is_match(&ent));
+                if current_name.as_ref() == Some(&ent.os_name) {
+                    current_lost = true;
+                }
+                (ent, is_visible)
+            };
+            if is_visible {
+                // Like clamp_current(), a lost cursor goes to the next
+                // visible entry, or to its own if it came back.
+                if current_lost {
+                    self.current = Some(idx);
+                    current_lost = false;
+                }
+                new_visible.push(idx);
+            }
+            self.entries.push(ent);
+        }
+        self.marked = new_marked.into_iter().collect();
+        self.flagged = new_flagged.into_iter().collect();
+        self.visible = new_visible;
+        if current_lost {
+            self.current = self.visible.last().copied();
+        }
+    }
+
+    pub fn new(dir_path: PathBuf, show_hidden: bool, cx: &mut Context<Self>) -> Self {
+        let sort = cx.global::<AppGlobal>().default_sort;
+        let search_mode = cx.global::<AppGlobal>().default_search_mode;
+        let mut model = Self {
+            entries: vec![],
+            sort,
             current: None,
             marked: BTreeSet::new(),
+            flagged: BTreeSet::new(),
+            visible: vec![],
+            filter: String::new(),
+            filter_matcher: None,
             dir_path,
             history: vec![],
             start_with: String::new(),
+            search_origin: None,
+            search_stack: vec![],
+            search_mode,
+            matcher: Matcher::new("", search_mode, sort.natural),
             show_hidden,
-        }
+            trash: vec![],
+            find: None,
+            previews: HashMap::new(),
+            watching: None,
+            watch_task: None,
+            loading: false,
+            fs_space: None,
+            fs_space_task: None,
+            fs_space_stale: false,
+            load_task: None,
+            pending_focus: None,
+            changed_while_loading: HashSet::new(),
+        };
+        // Even the first read happens off the UI thread, in case the
+        // directory is on a hung mount.
+        model.load_first(cx);
+        model.update_fs_space(cx);
+        model
     }
 
-    pub fn move_next(&mut self, _: &mut Context<Self>) {
-        if !self.entries.is_empty() {
-            self.current = Some(
-                self.current
-                    .map_or(0, |v| std::cmp::min(v + 1, self.entries.len() - 1)),
-            );
+    // Reads the directory the window was opened on. If that fails, opens
+    // $HOME, then /, and reports why.
+    fn load_first(&mut self, cx: &mut Context<Self>) {
+        let home = PathBuf::from(std::env::var_os("HOME").unwrap_or("/".into()));
+        let paths = vec![self.dir_path.clone(), home, PathBuf::from("/")];
+        self.load_any(paths, None, cx);
+    }
+
+    // The directory shown was deleted or moved away. Goes up to the nearest
+    // parent that is still there.
+    fn leave_gone_dir(&mut self, cx: &mut Context<Self>) {
+        self.watching = None;
+        let gone = self.dir_path.clone();
+        let paths = gone.ancestors().skip(1).map(Path::to_path_buf).collect();
+        self.load_any(paths, Some(LoadError::Gone(gone)), cx);
+    }
+
+    // Opens the first of paths that can be read. Reports reason, or else
+    // why the ones before it could not be read.
+    fn load_any(&mut self, paths: Vec<PathBuf>, reason: Option<LoadError>, cx: &mut Context<Self>) {
+        let options = self.list_options(cx);
+        self.loading = true;
+        self.load_task = Some(cx.spawn(async move |this, cx| {
+            let loaded = blocking(move || {
+                let mut first_err = None;
+                for path in paths {
+                    match Self::load_dir_result_partial(path, &options, None) {
+                        Ok(result) => return (Some(result), first_err),
+                        Err(err) => { first_err.get_or_insert(err); }
+                    }
+                }
+                (None, first_err)
+            }).await;
+            let Some((result, err)) = loaded else {
+                return;
+            };
+            let err = reason.or(err);
+            let _ = this.update(cx, |model, cx| {
+                model.loading = false;
+                if let Some(result) = result {
+                    model.refresh_with_result(result, cx);
+                }
+                if let Some(err) = err {
+                    cx.emit(LoadFailedEvent(err));
+                }
+                cx.notify();
+            });
+        }));
+    }
+
+    // Asks for the free space again, once the last answer is in and at
+    // most every FS_SPACE_INTERVAL. A hung mount leaves the old numbers
+    // showing and a single thread waiting on it.
+    fn update_fs_space(&mut self, cx: &mut Context<Self>) {
+        if self.fs_space_task.is_some() {
+            self.fs_space_stale = true;
+            return;
         }
+        self.fs_space_stale = false;
+        let path = self.dir_path.clone();
+        self.fs_space_task = Some(cx.spawn(async move |this, cx| {
+            let asked = path.clone();
+            let space = blocking(move || entry::fs_space(&asked).ok()).await.flatten();
+            let _ = this.update(cx, |model, cx| {
+                if model.dir_path == path {
+                    model.fs_space = space;
+                    cx.notify();
+                }
+            });
+            cx.background_executor().timer(FS_SPACE_INTERVAL).await;
+            let _ = this.update(cx, |model, cx| {
+                model.fs_space_task = None;
+                if model.fs_space_stale {
+                    model.update_fs_space(cx);
+                }
+            });
+        }));
     }
 
-    pub fn search_next(&mut self, _: &mut Context<Self>) -> bool {
-        let do_search = |this: &mut Self, range: Range<usize>| -> bool {
-            for idx in range {
-                if let Some(fname) = this.entries[idx].file_name().to_str() {
-                    if fname.starts_with(&this.start_with) {
-                        this.current = Some(idx);
-                        return true;
+    // How many of the visible entries are marked, and the sum of their
+    // sizes. Directories count as empty, as their contents were never read.
+    pub fn marked_size(&self) -> (usize, u64) {
+        self.marked.iter()
+            .filter(|idx| self.visible.binary_search(idx).is_ok())
+            .map(|idx| &self.entries[*idx])
+            .fold((0, 0), |(count, size), ent| (count + 1, size + if ent.is_dir() { 0 } else { ent.size }))
+    }
+
+    // Watches dir_path for changes made by other programs. Does nothing if
+    // we are already watching it.
+    pub fn watch(&mut self, cx: &mut Context<Self>) {
+        if self.find.is_none() && self.watching.as_ref() == Some(&self.dir_path) {
+            return;
+        }
+        self.watching = None;
+        self.watch_task = None;
+        // Changes deeper down would not show up in a find listing anyway.
+        if self.is_trash() || self.find.is_some() {
+            return;
+        }
+        self.watching = Some(self.dir_path.clone());
+
+        let path = self.dir_path.clone();
+        let mime_index = cx.global::<AppGlobal>().mime_index();
+        self.watch_task = Some(cx.spawn(async move |this, cx| {
+            // Adding the watch walks the path, which hangs on a dead mount.
+            // If we have moved on by the time it is added, this task is gone
+            // and the watcher is dropped with the thread's result.
+            let watch_path = path.clone();
+            let Some(Ok((_watcher, events))) = blocking(move || DirWatcher::new(&watch_path)).await else {
+                return;
+            };
+            while let Ok(first) = events.recv().await {
+                let mut batch = vec![first];
+                let start = Instant::now();
+                // Coalesce bursts: wait until things quiet down, but not forever.
+                loop {
+                    cx.background_executor().timer(Duration::from_millis(100)).await;
+                    let nr_events = batch.len();
+                    while let Ok(ev) = events.try_recv() {
+                        batch.push(ev);
                     }
+                    if batch.len() == nr_events || start.elapsed() > Duration::from_secs(1) {
+                        break;
+                    }
+                }
+
+                if batch.iter().any(|ev| matches!(ev, DirEvent::Gone)) {
+                    let _ = this.update(cx, |model, cx| model.leave_gone_dir(cx));
+                    break;
                 }
+                let overflow = batch.iter().any(|ev| matches!(ev, DirEvent::Overflow));
+                let touched: HashSet<OsString> = batch.into_iter().filter_map(|ev| match ev {
+                    DirEvent::Touched(name) => Some(name),
+                    DirEvent::Overflow | DirEvent::Gone => None,
+                }).collect();
+
+                let path = path.clone();
+                let mime_index = mime_index.clone();
+                let changes = blocking(move || Self::read_changes(&path, overflow, touched, &mime_index)).await.flatten();
+                let Some((changed, found)) = changes else {
+                    continue;
+                };
+
+                let updated = this.update(cx, |model, cx| {
+                    model.apply_dir_change(overflow, &changed, found);
+                    model.update_fs_space(cx);
+                    cx.notify();
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    // Stats the touched names. Returns the names that changed, and the
+    // entries of those still there. Names that cannot be stat'ed for other
+    // reasons are left as they are. Only when the kernel dropped events is
+    // the whole directory read again.
+    fn read_changes(path: &Path, overflow: bool, touched: HashSet<OsString>,
+                    mime_index: &MIMEGlobIndex) -> Option<(HashSet<OsString>, Vec<FileEntry>)> {
+        if overflow {
+            let found = std::fs::read_dir(path).ok()?
+                .filter_map(|e| e.ok())
+                .filter_map(|e| FileEntry::from_dir_entry(&e, mime_index).ok())
+                .collect();
+            return Some((HashSet::new(), found));
+        }
+        let mut changed = HashSet::new();
+        let mut found = vec![];
+        for name in touched {
+            match FileEntry::new(path.join(&name), mime_index) {
+                Ok(ent) => found.push(ent),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(_) => continue,
             }
-            return false;
+            changed.insert(name);
+        }
+        Some((changed, found))
+    }
+
+    // Applies entries re-read for the changed names, keeping current,
+    // marked and flagged on the same file names. Unless the whole directory
+    // was read again, only the changed entries are looked at by name.
+    fn apply_dir_change(&mut self, full: bool, changed: &HashSet<OsString>, found: Vec<FileEntry>) {
+        if self.loading {
+            // Names that were deleted count too, or a batch could bring
+            // them back.
+            self.changed_while_loading.extend(changed.iter().cloned());
+            self.changed_while_loading.extend(found.iter().map(|ent| ent.os_name.clone()));
+        }
+        let sort = self.sort;
+        let show_hidden = self.show_hidden;
+        let mut found: Vec<_> = found.into_iter().filter(|ent| show_hidden || !ent.is_hidden()).collect();
+        sort::sort_entries(&mut found, &sort);
+        if full {
+            self.keep_selection(|entries| *entries = found);
+        } else if !changed.is_empty() {
+            self.merge_sorted(found, changed);
+        }
+    }
+
+    fn names_of(&self, selected: &BTreeSet<usize>) -> HashSet<OsString> {
+        selected.iter().map(|idx| self.entries[*idx].os_name.clone()).collect()
+    }
+
+    fn indices_of(entries: &[FileEntry], names: &HashSet<OsString>) -> BTreeSet<usize> {
+        if names.is_empty() {
+            return BTreeSet::new();
+        }
+        entries.iter().enumerate()
+            .filter(|(_, ent)| names.contains(&ent.os_name))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    // Changes the entries, keeping current, marked and flagged on the same
+    // file names.
+    fn keep_selection(&mut self, change: impl FnOnce(&mut Vec<FileEntry>)) {
+        let cur_name = self.current.map(|idx| self.entries[idx].os_name.clone());
+        let marked_names = self.names_of(&self.marked);
+        let flagged_names = self.names_of(&self.flagged);
+
+        change(&mut self.entries);
+
+        self.marked = Self::indices_of(&self.entries, &marked_names);
+        self.flagged = Self::indices_of(&self.entries, &flagged_names);
+        if let Some(name) = cur_name {
+            self.focus_file_name(&name);
+        }
+        self.refilter();
+    }
+
+    fn refilter(&mut self) {
+        self.visible = match &self.filter_matcher {
+            Some(matcher) => (0..self.entries.len()).filter(|idx| matcher.is_match(&self.entries[*idx])).collect(),
+            None => (0..self.entries.len()).collect(),
         };
+        self.clamp_current();
+    }
 
-        if let Some(cur) = self.current {
-            do_search(self, (cur + 1)..self.entries.len()) || do_search(self, 0..(cur + 1))
-        } else {
-            do_search(self, 0..self.entries.len())
+    // Moves current to the next visible entry if it got filtered out.
+    fn clamp_current(&mut self) {
+        let Some(cur) = self.current else {
+            return;
+        };
+        if self.visible.binary_search(&cur).is_err() {
+            let pos = self.visible.partition_point(|idx| *idx < cur);
+            self.current = self.visible.get(pos).or(self.visible.last()).copied();
+        }
+    }
+
+    // Where current is among the visible entries.
+    pub fn current_pos(&self) -> Option<usize> {
+        self.current.and_then(|cur| self.visible.binary_search(&cur).ok())
+    }
+
+    // Shows only the entries matching query, or all of them if it is empty.
+    // Keeps the old filter if query does not compile.
+    pub fn set_filter(&mut self, query: String, _: &mut Context<Self>) -> Result<usize, String> {
+        self.apply_filter(query)
+    }
+
+    fn apply_filter(&mut self, query: String) -> Result<usize, String> {
+        self.filter_matcher = match query.is_empty() {
+            true => None,
+            false => Some(Matcher::new(&query, self.search_mode, self.sort.natural)?),
+        };
+        self.filter = query;
+        self.refilter();
+        if self.current.is_none() {
+            self.current = self.visible.first().copied();
+        }
+        Ok(self.visible.len())
+    }
+
+    pub fn clear_filter(&mut self, _: &mut Context<Self>) {
+        let _ = self.apply_filter(String::new());
+    }
+
+    // The filter matches the way searches do, so follows their options.
+    fn compile_filter(&mut self) {
+        if self.apply_filter(self.filter.clone()).is_err() {
+            let _ = self.apply_filter(String::new());
+        }
+    }
+
+    pub fn is_trash(&self) -> bool {
+        self.dir_path.as_os_str() == TRASH_LOCATION
+    }
+
+    // Trashed entries are named after where they came from.
+    pub fn label(&self, idx: usize) -> Option<String> {
+        Some(self.entries[idx].name.clone())
+    }
+
+    // Extra lines shown under the label of the current item.
+    pub fn detail(&self, idx: usize) -> Option<String> {
+        if self.is_trash() {
+            let info = &self.trash[idx];
+            return Some(format!("{}\nDeleted {}", info.original.display(), info.deletion_date));
+        }
+        if let Some(previews) = self.previews.get(&self.entries[idx].path) {
+            return Some(previews.join("\n"));
+        }
+        self.entries[idx].symlink_target.as_ref().map(|target| format!("-> {}", target.display()))
+    }
+
+    pub fn move_next(&mut self, _: &mut Context<Self>) {
+        if !self.visible.is_empty() {
+            let pos = self.current_pos().map_or(0, |v| std::cmp::min(v + 1, self.visible.len() - 1));
+            self.current = Some(self.visible[pos]);
+        }
+    }
+
+    // Recompiles the matcher after the query, mode or sort changed.
+    fn compile_search(&mut self) {
+        self.matcher = Matcher::new(&self.start_with, self.search_mode, self.sort.natural);
+    }
+
+    fn set_query(&mut self, query: String) {
+        self.start_with = query;
+        self.compile_search();
+    }
+
+    // Moves to the first match going forward or backward from position
+    // start in visible, wrapping around. Stays put if nothing matches.
+    fn search_from(&mut self, start: usize, forward: bool) -> Result<bool, String> {
+        let matcher = self.matcher.as_ref().map_err(|err| err.clone())?;
+        let len = self.visible.len();
+        if len == 0 {
+            return Ok(false);
+        }
+        let found = (0..len)
+            .map(|i| self.visible[if forward { (start + i) % len } else { (start + len - i) % len }])
+            .find(|idx| matcher.is_match(&self.entries[*idx]));
+        if found.is_some() {
+            self.current = found;
+        }
+        Ok(found.is_some())
+    }
+
+    fn current_name(&self) -> Option<OsString> {
+        self.current.map(|idx| self.entries[idx].os_name.clone())
+    }
+
+    fn search_push(&mut self) {
+        self.search_stack.push((self.start_with.clone(), self.current_name()));
+    }
+
+    pub fn search_next(&mut self, _: &mut Context<Self>) -> Result<bool, String> {
+        self.search_push();
+        let start = self.current_pos().map_or(0, |cur| cur + 1);
+        self.search_from(start, true)
+    }
+
+    pub fn search_prev(&mut self, _: &mut Context<Self>) -> Result<bool, String> {
+        self.search_push();
+        let len = self.visible.len();
+        let start = self.current_pos().map_or(len.saturating_sub(1), |cur| cur + len.saturating_sub(1));
+        self.search_from(start, false)
+    }
+
+    pub fn search_start(&mut self, _: &mut Context<Self>) {
+        self.set_query(String::new());
+        self.search_origin = self.current_name();
+        self.search_stack.clear();
+    }
+
+    // The query was edited: match it again from where the search started.
+    pub fn search_update(&mut self, query: String, _: &mut Context<Self>) -> Result<bool, String> {
+        self.search_push();
+        self.set_query(query);
+        if let Some(origin) = self.search_origin.clone() {
+            self.focus_file_name(&origin);
+            self.clamp_current();
+        }
+        let start = self.current_pos().unwrap_or(0);
+        self.search_from(start, true)
+    }
+
+    // Undoes the last isearch step, giving back the query it had and
+    // whether the cursor is on a match of it.
+    pub fn search_undo(&mut self, _: &mut Context<Self>) -> Option<(String, Result<bool, String>)> {
+        let (query, current) = self.search_stack.pop()?;
+        self.set_query(query.clone());
+        if let Some(name) = current {
+            self.focus_file_name(&name);
+            self.clamp_current();
         }
+        let found = match &self.matcher {
+            Ok(matcher) => Ok(self.current.is_some_and(|idx| matcher.is_match(&self.entries[idx]))),
+            Err(err) => Err(err.clone()),
+        };
+        Some((query, found))
+    }
+
+    pub fn set_search_mode(&mut self, mode: SearchMode, _: &mut Context<Self>) {
+        self.search_mode = mode;
+        self.compile_search();
+        self.compile_filter();
+    }
+
+    // The part of the label that the search matched, for highlighting.
+    pub fn search_match_range(&self, idx: usize) -> Option<Range<usize>> {
+        if self.start_with.is_empty() {
+            return None;
+        }
+        self.matcher.as_ref().ok()?.find(&self.entries[idx])
     }
 
     pub fn search_clear(&mut self, _: &mut Context<Self>) {
-        self.start_with.clear();
+        self.set_query(String::new());
+        self.search_origin = None;
+        self.search_stack.clear();
     }
 
     pub fn move_prev(&mut self, _: &mut Context<Self>) {
         self.current = self
-            .current
-            .map_or(None, |v| Some(if v == 0 { 0 } else { v - 1 }));
+            .current_pos()
+            .map(|v| self.visible[if v == 0 { 0 } else { v - 1 }]);
+    }
+
+    // Moves lines rows up or down the grid of per_line items, staying in the
+    // same column. Stops at the first and last rows, going to the last item
+    // if the last row is too short.
+    pub fn move_lines(&mut self, lines: isize, per_line: usize, _: &mut Context<Self>) {
+        let Some(pos) = self.current_pos() else {
+            self.current = self.visible.first().copied();
+            return;
+        };
+        let per_line = per_line.max(1);
+        let last_line = (self.visible.len() - 1) / per_line;
+        let line = (pos / per_line).saturating_add_signed(lines).min(last_line);
+        let pos = (line * per_line + pos % per_line).min(self.visible.len() - 1);
+        self.current = Some(self.visible[pos]);
+    }
+
+    // Moves within the current row, stopping at its ends.
+    pub fn move_columns(&mut self, columns: isize, per_line: usize, _: &mut Context<Self>) {
+        let Some(pos) = self.current_pos() else {
+            self.current = self.visible.first().copied();
+            return;
+        };
+        let per_line = per_line.max(1);
+        let line_start = pos / per_line * per_line;
+        let line_end = (line_start + per_line).min(self.visible.len()) - 1;
+        let pos = pos.saturating_add_signed(columns).clamp(line_start, line_end);
+        self.current = Some(self.visible[pos]);
     }
 
     pub fn move_home(&mut self, _: &mut Context<Self>) {
-        if !self.entries.is_empty() {
-            self.current = Some(0);
-        }
+        self.current = self.visible.first().copied();
     }
 
     pub fn move_end(&mut self, _: &mut Context<Self>) {
-        if !self.entries.is_empty() {
-            self.current = Some(self.entries.len() - 1);
+        self.current = self.visible.last().copied();
+    }
+
+    // Makes idx current, unless the filter hides it.
+    pub fn select(&mut self, idx: usize, _: &mut Context<Self>) {
+        if self.visible.binary_search(&idx).is_ok() {
+            self.current = Some(idx);
         }
     }
 
+    // The entry at idx, if it is still listed and not filtered out. Items
+    // clicked on were drawn some time before.
+    pub fn visible_entry(&self, idx: usize) -> Option<&FileEntry> {
+        self.visible.binary_search(&idx).ok()?;
+        self.entries.get(idx)
+    }
+
+    pub fn toggle_mark_at(&mut self, idx: usize, cx: &mut Context<Self>) {
+        self.select(idx, cx);
+        if self.current == Some(idx) && !self.marked.remove(&idx) {
+            self.marked.insert(idx);
+        }
+    }
+
+    // Marks the visible entries from current through idx, which becomes
+    // current.
+    pub fn mark_range(&mut self, idx: usize, cx: &mut Context<Self>) {
+        let Ok(to) = self.visible.binary_search(&idx) else {
+            return;
+        };
+        let from = self.current_pos().unwrap_or(to);
+        self.marked.extend(self.visible[from.min(to)..=from.max(to)].iter().copied());
+        self.select(idx, cx);
+    }
+
+    // The marks a rubber band starts from: all of them when adding to the
+    // marks, otherwise only those hidden by the filter.
+    pub fn band_base(&self, add: bool) -> BTreeSet<usize> {
+        self.marked.iter().copied()
+            .filter(|idx| add || self.visible.binary_search(idx).is_err())
+            .collect()
+    }
+
+    // Marks base and the entries at the given positions in visible.
+    pub fn mark_band(&mut self, base: &BTreeSet<usize>, positions: impl Iterator<Item = usize>) {
+        let mut marked = base.clone();
+        marked.extend(positions.filter_map(|pos| self.visible.get(pos).copied()));
+        self.marked = marked;
+    }
+
     pub fn toggle_mark(&mut self, cx: &mut Context<Self>) {
         if let Some(cur) = self.current {
             if self.marked.contains(&cur) {
@@ -291,27 +1280,123 @@ impl DirModel {
                 self.marked.insert(cur);
             }
         }
-        self.move_next(cx);
+        self.move_next(cx);
+    }
+
+    // Like Dired, unmarking also drops the deletion flag.
+    pub fn unmark(&mut self, cx: &mut Context<Self>) {
+        if let Some(cur) = self.current {
+            self.marked.remove(&cur);
+            self.flagged.remove(&cur);
+        }
+        self.move_next(cx);
+    }
+
+    pub fn flag(&mut self, cx: &mut Context<Self>) {
+        if let Some(cur) = self.current {
+            self.flagged.insert(cur);
+        }
+        self.move_next(cx);
+    }
+
+    fn flag_where(&mut self, pred: impl Fn(&[u8]) -> bool) -> usize {
+        let before = self.flagged.len();
+        let entries = &self.entries;
+        self.flagged.extend(self.visible.iter().copied().filter(|idx| {
+            // Find listings name entries by their relative paths.
+            let name = entries[*idx].path.file_name().unwrap_or_default();
+            pred(name.as_encoded_bytes())
+        }));
+        self.flagged.len() - before
+    }
+
+    // Backups like foo~ and foo.~1~.
+    pub fn flag_backups(&mut self, _: &mut Context<Self>) -> usize {
+        self.flag_where(|name| name.ends_with(b"~"))
+    }
+
+    // Emacs autosaves like #foo#.
+    pub fn flag_autosaves(&mut self, _: &mut Context<Self>) -> usize {
+        self.flag_where(|name| name.len() > 1 && name.starts_with(b"#") && name.ends_with(b"#"))
+    }
+
+    // Marks the visible entries for which pred holds. Returns how many were
+    // not marked before.
+    fn mark_where(&mut self, pred: impl Fn(&FileEntry) -> bool) -> usize {
+        let before = self.marked.len();
+        let entries = &self.entries;
+        self.marked.extend(self.visible.iter().copied().filter(|idx| pred(&entries[*idx])));
+        self.marked.len() - before
+    }
+
+    pub fn mark_regex(&mut self, pattern: &str, _: &mut Context<Self>) -> Result<usize, String> {
+        let re = regex::Regex::new(pattern).map_err(|err| search::regex_error(&err))?;
+        Ok(self.mark_where(|ent| re.is_match(&ent.name)))
+    }
+
+    pub fn current_extension(&self) -> Option<String> {
+        let ent = &self.entries[self.current?];
+        Path::new(&ent.os_name).extension().map(entry::escape_name)
+    }
+
+    pub fn mark_extension(&mut self, ext: &str, _: &mut Context<Self>) -> usize {
+        let ext = entry::unescape_name(ext.trim_start_matches('.'));
+        self.mark_where(|ent| Path::new(&ent.os_name).extension() == Some(ext.as_os_str()))
+    }
+
+    pub fn mark_dirs(&mut self, _: &mut Context<Self>) -> usize {
+        self.mark_where(|ent| ent.is_dir())
+    }
+
+    pub fn mark_symlinks(&mut self, _: &mut Context<Self>) -> usize {
+        self.mark_where(|ent| ent.is_symlink())
+    }
+
+    pub fn mark_executables(&mut self, _: &mut Context<Self>) -> usize {
+        self.mark_where(|ent| ent.file_type.is_file() && ent.permissions.mode() & 0o111 != 0)
+    }
+
+    // Like everything else, only touches the visible entries.
+    pub fn invert_marks(&mut self, _: &mut Context<Self>) {
+        let mut marked = std::mem::take(&mut self.marked);
+        for idx in &self.visible {
+            if !marked.remove(idx) {
+                marked.insert(*idx);
+            }
+        }
+        self.marked = marked;
     }
 
-    pub fn toggle_hidden(&mut self, _cx: &mut Context<Self>) {
-        self.show_hidden = !self.show_hidden;
-        self.marked = BTreeSet::new();
-        let cur_filename = self.current.map(|idx| self.entries[idx].file_name());
-        self.entries = Self::load_entries(&self.dir_path, self.show_hidden);
-        if let Some(last_filename) = cur_filename {
-            self.current = self.entries.iter().position(|ent| ent.file_name() == last_filename);
+    pub fn unmark_all(&mut self, _: &mut Context<Self>) {
+        if self.visible.len() == self.entries.len() {
+            self.marked.clear();
+            self.flagged.clear();
+        } else {
+            for idx in &self.visible {
+                self.marked.remove(idx);
+                self.flagged.remove(idx);
+            }
         }
     }
 
+    pub fn toggle_hidden(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        self.reread(ListOptions { show_hidden: !self.show_hidden, ..self.list_options(cx) }, cx)
+    }
+
+    // If the re-read fails, we keep showing what we have.
+    pub fn hidden_with_result(&mut self, show_hidden: bool, result: OpenDirResult, cx: &mut Context<Self>) {
+        self.show_hidden = show_hidden;
+        self.refresh_with_result(result, cx);
+    }
+
     pub fn should_open_dir(&self) -> Option<bool> {
-        self.current.and_then(|idx| self.entries[idx].file_type().ok().map(|t| t.is_dir()))
+        self.current.map(|idx| self.entries[idx].is_dir())
     }
 
     #[cfg(target_os = "macos")]
     pub fn open_file(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<Option<(String, usize)>>, String> {
         let cur_idx = self.current.expect("BUG: use should_open_dir()");
-        let filename = self.entries[cur_idx].path().to_str().unwrap().to_string();
+        let filename = self.entries[cur_idx].path.clone();
         IOWorker::spawn(
             cx.background_executor(),
             "Open file",
@@ -326,8 +1411,7 @@ impl DirModel {
     #[cfg(target_os = "linux")]
     pub fn open_file(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<Option<(String, usize)>>, String> {
         let cur_idx = self.current.expect("BUG: use should_open_dir()");
-        let mime = cx.global::<AppGlobal>().match_mime_type(
-            self.entries[cur_idx].file_name().to_str().unwrap());
+        let mime = self.entries[cur_idx].mime.clone();
 
         let Some(assoc) = cx.global::<AppGlobal>().get_mime_assoc_index(&mime) else {
             return IOWorker::err("Cannot find an application to open this file.");
@@ -348,11 +1432,17 @@ impl DirModel {
             }
         }).collect::<Vec<_>>();
 
+        // Command lines are Strings, which cannot hold paths that are not
+        // UTF-8. Expand the field codes around a placeholder, and put the raw
+        // path where it lands once the line is split into arguments.
+        let placeholder = PathBuf::from("/forg-path-placeholder");
+        let path = self.entries[cur_idx].path.clone();
         let cmds = all.iter().map(|idx| {
-            let path = self.entries[cur_idx].path();
-            let v = vec![&path];
+            let v = vec![&placeholder];
             cx.global::<AppGlobal>().get_menu_item(*idx).detail_entry().unwrap().exec_with_filenames(&v)
-        }).flatten().collect::<Vec<_>>();
+        }).flatten().map(|cmd| {
+            exec_args(&cmd, placeholder.to_str().unwrap(), &path)
+        }).collect::<Vec<_>>();
 
         return IOWorker::spawn(
             cx.background_executor(),
@@ -377,7 +1467,10 @@ impl DirModel {
                     return Err("Did not selection an application".to_string());
                 };
 
-                if let Err(err) = Command::new("/bin/sh").arg("-c").arg(&cmds[sel_option]).spawn() {
+                let Some((program, args)) = cmds[sel_option].split_first() else {
+                    return Err("The application has no command to run".to_string());
+                };
+                if let Err(err) = Command::new(program).args(args).spawn() {
                     return Err(err.to_string());
                 }
                 if response.action == 0 {
@@ -412,80 +1505,171 @@ impl DirModel {
         };
 
         let cur_ent = &self.entries[self.current.expect("BUG: use should_open_dir")];
-        let Ok(file_type) = cur_ent.file_type() else {
-            return IOWorker::err("Cannot determine file type");
-        };
-
-        let target_path = cur_ent.path().clone();
-        let show_hidden = self.show_hidden;
+        let file_type = cur_ent.file_type;
+        let target_path = cur_ent.path.clone();
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let what = entry::escape_name(target_path.as_os_str());
         IOWorker::spawn(
             cx.background_executor(),
             "Reading directory...",
-            |ui_send, _input_recv| async move {
-                ui_send.close();
-                if file_type.is_dir() {
-                    let entries = Self::load_entries(&target_path, show_hidden);
-                    return Ok(OpenDirResult {
-                        path:target_path,
-                        entries,
-                        current: None
-                    });
-                } else if file_type.is_symlink() {
-                    if let Ok(path) = resolve_symlink(target_path) {
-                        if !path.exists() {
-                            return Err(format!("Cannot follow {}", path.display()));
-                        }
-                        if path.is_dir() {
-                            let entries = Self::load_entries(&path, show_hidden);
-                            return Ok(OpenDirResult {
-                                path,
-                                entries,
-                                current: None,
-                            });
-                        } else if path.is_file() {
-                            let focus = path.file_name().map(|x| x.to_owned());
-                            let path = path.parent().unwrap().to_path_buf();
-                            let entries = Self::load_entries(&path, show_hidden);
-                            return Ok(OpenDirResult {
-                                path,
-                                entries,
-                                current: focus,
-                            })
+            |ui_send, input_recv| async move {
+                worker_blocking(what, timeout, move || {
+                    if file_type.is_dir() {
+                        return Self::load_dir_result_partial(target_path, &options, None).map_err(|err| err.to_string());
+                    } else if file_type.is_symlink() {
+                        if let Ok(path) = resolve_symlink(target_path) {
+                            if !path.exists() {
+                                return Err(format!("Cannot follow {}", path.display()));
+                            }
+                            if path.is_dir() {
+                                return Self::load_dir_result_partial(path, &options, None).map_err(|err| err.to_string());
+                            } else if path.is_file() {
+                                let focus = path.file_name().map(|x| x.to_owned());
+                                let path = path.parent().unwrap().to_path_buf();
+                                return Self::load_dir_result_partial(path, &options, focus).map_err(|err| err.to_string())
+                            }
                         }
                     }
-                }
-                return Err("Do not know how to handle this item".to_string());
+                    return Err("Do not know how to handle this item".to_string());
+                }, &ui_send, &input_recv).await
             })
     }
 
-    pub fn refresh_with_result(&mut self, result: OpenDirResult) {
+    // After an operation, the marks it acted on are done with. Flags are
+    // kept until x deletes what they are on.
+    pub fn refresh_with_result(&mut self, result: OpenDirResult, cx: &mut Context<Self>) {
+        self.apply_result(result, false, cx);
+    }
+
+    // Like refresh_with_result, but keeps the marks on the same names and
+    // counts what changed, for g.
+    pub fn revert_with_result(&mut self, result: OpenDirResult, cx: &mut Context<Self>) -> RefreshStats {
+        self.apply_result(result, true, cx)
+    }
+
+    fn apply_result(&mut self, result: OpenDirResult, keep_marks: bool, cx: &mut Context<Self>) -> RefreshStats {
+        self.stop_loading(cx);
+        let mut stats = RefreshStats { appeared: 0, disappeared: 0 };
+
+        let same_dir = self.dir_path == result.path && self.find == result.find;
+        if same_dir && keep_marks {
+            let old_names: HashSet<OsString> = self.entries.iter().map(|ent| ent.os_name.clone()).collect();
+            let new_names: HashSet<OsString> = result.entries.iter().map(|ent| ent.os_name.clone()).collect();
+            stats.appeared = new_names.difference(&old_names).count();
+            stats.disappeared = old_names.difference(&new_names).count();
+            self.marked = Self::indices_of(&result.entries, &self.names_of(&self.marked));
+        } else {
+            self.marked = BTreeSet::new();
+        }
+        // Like Dired, flags stay on what was not deleted.
+        self.flagged = match same_dir {
+            true => Self::indices_of(&result.entries, &self.names_of(&self.flagged)),
+            false => BTreeSet::new(),
+        };
+        if !same_dir {
+            self.filter.clear();
+            self.filter_matcher = None;
+        }
+
         self.dir_path = result.path;
-        self.marked = BTreeSet::new();
         self.entries = result.entries;
+        self.trash = result.trash;
+        self.find = result.find;
+        self.previews = result.previews;
         if let Some(name) = result.current {
-            self.focus_file_name(&name);
+            if !self.focus_file_name(&name) && result.rest.is_some() {
+                self.pending_focus = Some(name);
+            }
+        }
+        if self.current.is_some_and(|cur| cur >= self.entries.len()) {
+            self.current = self.entries.len().checked_sub(1);
         }
+        self.refilter();
+        if let Some(rest) = result.rest {
+            self.stream_entries(rest, cx);
+        }
+        self.watch(cx);
+        self.update_fs_space(cx);
+        stats
     }
 
-    pub fn open_with_result(&mut self, result: OpenDirResult) {
+    pub fn open_with_result(&mut self, result: OpenDirResult, cx: &mut Context<Self>) {
         let path = std::mem::take(&mut self.dir_path);
-        let current = std::mem::take(&mut self.current).map(|idx| self.entries[idx].file_name());
-        self.history.push(DirHistoryItem { current, path });
-        self.refresh_with_result(result);
+        self.push_history(path);
+        self.refresh_with_result(result, cx);
+    }
+
+    fn push_history(&mut self, path: PathBuf) {
+        let find = self.find_listing().map(|find| FindListing { root: path.clone(), ..find });
+        let current = std::mem::take(&mut self.current).map(|idx| self.entries[idx].os_name.clone());
+        self.history.push(DirHistoryItem { current, path, find });
+    }
+
+    // Lists the text files under dir_path with lines matching the regex
+    // pattern, as they are found. Back returns to the directory.
+    pub fn find_grep(&mut self, pattern: String, cx: &mut Context<Self>) -> Result<(), String> {
+        if self.is_trash() {
+            return Err("Cannot grep in the trash".to_string());
+        }
+        let re = regex::bytes::Regex::new(&pattern).map_err(|err| search::regex_error(&err))?;
+        let options = ListOptions { find: None, ..self.list_options(cx) };
+        self.start_listing(format!("grep {}", pattern), cx);
+
+        let root = self.dir_path.clone();
+        let (send, recv) = smol::channel::bounded(1);
+        std::thread::spawn(move || find::grep(root, re, options, send));
+        self.stream_batches(recv, cx);
+        Ok(())
+    }
+
+    // Lists the entries under dir_path whose names match pattern, a glob or
+    // with regex, a regex. Back returns to the directory.
+    pub fn find_name(&mut self, pattern: String, regex: bool, cx: &mut Context<Self>) -> Result<(), String> {
+        if self.is_trash() {
+            return Err("Cannot find in the trash".to_string());
+        }
+        let re = find::name_matcher(&pattern, regex)?;
+        let options = ListOptions { find: None, ..self.list_options(cx) };
+        self.start_listing(format!("find {}", pattern), cx);
+
+        let root = self.dir_path.clone();
+        let (send, recv) = smol::channel::bounded(1);
+        std::thread::spawn(move || find::walk(root, re, options, send));
+        self.stream_batches(recv, cx);
+        Ok(())
+    }
+
+    // Empties the view for a listing that batches will fill in.
+    fn start_listing(&mut self, find: String, cx: &mut Context<Self>) {
+        self.stop_loading(cx);
+        self.push_history(self.dir_path.clone());
+        self.entries.clear();
+        self.marked.clear();
+        self.flagged.clear();
+        self.filter.clear();
+        self.filter_matcher = None;
+        self.find = Some(find);
+        self.previews.clear();
+        self.refilter();
+        self.watch(cx);
     }
 
-    pub fn back_with_result(&mut self, result: OpenDirResult) {
+    pub fn back_with_result(&mut self, result: OpenDirResult, cx: &mut Context<Self>) {
         self.history.pop();
-        self.refresh_with_result(result);
+        self.refresh_with_result(result, cx);
     }
 
-    pub fn focus_file_name(&mut self, name: &OsStr) {
+    // The entry may be filtered out. Callers refilter() or clamp_current()
+    // after, so the cursor only rests on visible entries.
+    pub fn focus_file_name(&mut self, name: &OsStr) -> bool {
         for i in 0..self.entries.len() {
-            if self.entries[i].file_name() == name {
+            if self.entries[i].os_name == name {
                 self.current = Some(i);
-                break;
+                return true;
             }
         }
+        false
     }
 
     pub fn back(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
@@ -494,20 +1678,17 @@ impl DirModel {
         };
         let path = ent.path.clone();
         let current = ent.current.clone();
-        let show_hidden = self.show_hidden;
+        let options = ListOptions { find: ent.find.clone(), ..self.list_options(cx) };
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let what = entry::escape_name(path.as_os_str());
 
         return IOWorker::spawn(
             cx.background_executor(),
             "Going back. Reading directory...",
-            |ui_send, _input_recv| async move {
-                // No need to report progress.
-                ui_send.close();
-                let entries = Self::load_entries(&path, show_hidden);
-                Ok(OpenDirResult {
-                    path,
-                    entries,
-                    current,
-                })
+            |ui_send, input_recv| async move {
+                worker_blocking(what, timeout, move || {
+                    Self::load_dir_result_partial(path, &options, current).map_err(|err| err.to_string())
+                }, &ui_send, &input_recv).await
             });
     }
 
@@ -516,26 +1697,28 @@ impl DirModel {
         if !path.pop() {
             return IOWorker::err(format!("Cannot go to the parent dir. {}", path.display()).as_str());
         }
-        let show_hidden = self.show_hidden;
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let what = entry::escape_name(path.as_os_str());
         return IOWorker::spawn(
             cx.background_executor(),
             "Moving up. Reading directory...",
-            |ui_send, _input_recv| async move {
-                ui_send.close();
-                let entries = Self::load_entries(&path, show_hidden);
-                Ok(OpenDirResult {
-                    path,
-                    entries,
-                    current: None,
-                })
+            |ui_send, input_recv| async move {
+                worker_blocking(what, timeout, move || {
+                    Self::load_dir_result_partial(path, &options, None).map_err(|err| err.to_string())
+                }, &ui_send, &input_recv).await
             });
     }
 
+    // Marks hidden by the filter are kept, but not acted on.
     fn operate_items(&self) -> Vec<usize> {
-        if self.marked.is_empty() {
+        let marked: Vec<usize> = self.marked.iter().cloned()
+            .filter(|idx| self.visible.binary_search(idx).is_ok())
+            .collect();
+        if marked.is_empty() {
             self.current.iter().cloned().collect()
         } else {
-            self.marked.iter().cloned().collect()
+            marked
         }
     }
 
@@ -557,8 +1740,8 @@ impl DirModel {
             }
 
             let ent_name_osstring = p.file_name().unwrap();
-            let ent_name = prefix_dir.to_string() + ent_name_osstring.to_str().unwrap_or("");
-            let Ok(metadata) = p.symlink_metadata() else {
+            let ent_name = prefix_dir.to_string() + &entry::escape_name(ent_name_osstring);
+            let Ok(metadata) = blocking_io({ let p = p.clone(); move || p.symlink_metadata() }).await else {
                 worker_error(format!("Cannot read metadata of {}", ent_name).into(), ui_send, input_recv).await;
                 continue;
             };
@@ -571,7 +1754,7 @@ impl DirModel {
                     continue;
                 }
 
-                let Ok(next_to_delete) = Self::load_entry_as_paths(&p) else {
+                let Ok(next_to_delete) = blocking_io({ let p = p.clone(); move || Self::load_entry_as_paths(&p) }).await else {
                     worker_error(format!("Cannot read dir {}.", ent_name).into(), ui_send, input_recv).await;
                     continue;
                 };
@@ -586,7 +1769,7 @@ impl DirModel {
                     continue;
                 }
 
-                if let Err(err) = std::fs::remove_dir(&p) {
+                if let Err(err) = blocking_io(move || std::fs::remove_dir(p)).await {
                     worker_error(format!("Cannot remove dir {}. {}", ent_name, err).into(), ui_send, input_recv).await;
                     continue;
                 }
@@ -601,7 +1784,7 @@ impl DirModel {
 
                 worker_progress(format!("Deleting {}", ent_name).into(), last_progress_ts, ui_send).await;
 
-                if let Err(err) = std::fs::remove_file(p.as_path()) {
+                if let Err(err) = blocking_io(move || std::fs::remove_file(p)).await {
                     worker_error(format!("Cannot remove file {}, {}", ent_name, err).into(), ui_send, input_recv).await;
                     continue;
                 }
@@ -613,16 +1796,31 @@ impl DirModel {
     }
 
 
+    // Deleting from the trash also drops the .trashinfo records.
+    fn remove_trash_infos(trash_infos: Vec<(PathBuf, PathBuf)>) {
+        for (file_path, info_path) in trash_infos {
+            if file_path.symlink_metadata().is_err() {
+                let _ = std::fs::remove_file(info_path);
+            }
+        }
+    }
+
     pub fn delete(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
         let to_delete = self.operate_items();
         if to_delete.is_empty() {
             return IOWorker::err("Nothing to delete");
         }
 
-        let to_delete: Vec<_> = to_delete.iter().map(|idx| self.entries[*idx].path()).collect();
+        let trash_infos: Vec<_> = if self.is_trash() {
+            to_delete.iter().map(|idx| (self.trash[*idx].file_path.clone(), self.trash[*idx].info_path.clone())).collect()
+        } else {
+            vec![]
+        };
+        let to_delete: Vec<_> = to_delete.iter().map(|idx| self.entries[*idx].path.clone()).collect();
         let path = self.dir_path.clone();
-        let current = self.current.map(|cur| self.entries[cur].file_name().clone());
-        let show_hidden = self.show_hidden;
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
 
         return IOWorker::spawn(
             cx.background_executor(),
@@ -640,40 +1838,328 @@ impl DirModel {
                     &mut last_progress_ts,
                     &exception_set).await;
 
-                let entries = Self::load_entries(&path, show_hidden);
-                Ok(OpenDirResult {
-                    path,
-                    entries,
-                    current,
-                })
+                Self::remove_trash_infos(trash_infos);
+
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
+            });
+    }
+
+    // Deletes everything flagged for good after asking once. Like marks,
+    // flags hidden by the filter are left alone.
+    pub fn execute_flagged(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let flagged: Vec<usize> = self.flagged.iter().cloned()
+            .filter(|idx| self.visible.binary_search(idx).is_ok())
+            .collect();
+        if flagged.is_empty() {
+            return IOWorker::err("No flagged items");
+        }
+
+        let is_trash = self.is_trash();
+        let trash_infos: Vec<_> = if is_trash {
+            flagged.iter().map(|idx| (self.trash[*idx].file_path.clone(), self.trash[*idx].info_path.clone())).collect()
+        } else {
+            vec![]
+        };
+        let options = flagged.iter().map(|idx| {
+            DialogOption {
+                text: self.entries[*idx].name.clone().into(),
+                icon_name: self.entries[*idx].mime.replace('/', "-"),
+            }
+        }).collect::<Vec<_>>();
+        let msg = format!("Permanently delete {} flagged item{}?",
+                          flagged.len(), if flagged.len() == 1 { "" } else { "s" });
+        let to_delete: Vec<_> = flagged.iter().map(|idx| self.entries[*idx].path.clone()).collect();
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let list_options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Deleting flagged items...",
+            |ui_send, input_recv| async move {
+                let response = worker_dialog(DialogRequest {
+                    msg: msg.into(),
+                    actions: vec![DialogAction::new("Yes", "enter"), DialogAction::new("Cancel", "ctrl-g")],
+                    sel_option: None,
+                    options,
+                }, &ui_send, &input_recv).await.unwrap();
+                if response.action != 0 {
+                    return Self::reload(path, list_options, current, timeout, &ui_send, &input_recv).await;
+                }
+
+                let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
+                Self::delete_dir_entries(
+                    &ui_send, &input_recv,
+                    "", to_delete,
+                    &mut Some(true), &mut Some(true),
+                    &mut last_progress_ts,
+                    &BTreeSet::new()).await;
+                Self::remove_trash_infos(trash_infos);
+
+                Self::reload(path, list_options, current, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    pub fn trash(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        if self.is_trash() {
+            return IOWorker::err("Already in the trash. Use shift-d to delete permanently.");
+        }
+        let to_trash = self.operate_items();
+        if to_trash.is_empty() {
+            return IOWorker::err("Nothing to move to trash");
+        }
+
+        let to_trash: Vec<_> = to_trash.iter().map(|idx| self.entries[*idx].path.clone()).collect();
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let journal = cx.global::<AppGlobal>().journal();
+
+        return IOWorker::spawn(
+            cx.background_executor(),
+            "Moving to trash...",
+            |ui_send, input_recv| async move {
+                let mut response: Option<bool> = None;
+                let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
+                let ops = Self::trash_paths(to_trash, &mut response, &mut last_progress_ts, &ui_send, &input_recv).await;
+                journal.record(ops);
+
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
             });
     }
 
+    async fn trash_paths(to_trash: Vec<PathBuf>, response: &mut Option<bool>, last_progress_ts: &mut SystemTime,
+                         ui_send: &Sender<DialogRequest>, input_recv: &Receiver<DialogResponse>) -> Vec<JournalEntry> {
+        let mut ops = vec![];
+        for p in to_trash {
+            if worker_should_exit(input_recv).await {
+                break;
+            }
+
+            let ent_name = entry::escape_name(p.file_name().unwrap());
+            let should_trash = worker_multi_yes_no(
+                format!("Move {} to trash?", ent_name).into(),
+                response, ui_send, input_recv).await;
+            if !should_trash {
+                continue;
+            }
+
+            worker_progress(format!("Trashing {}", ent_name).into(), last_progress_ts, ui_send).await;
+
+            match blocking_io({ let p = p.clone(); move || trash::move_to_trash(&p) }).await {
+                Ok(trashed) => ops.push(JournalEntry::new(OpKind::Trash, p, trashed)),
+                Err(err) => {
+                    worker_error(format!("Cannot move {} to trash. {}", ent_name, err).into(), ui_send, input_recv).await;
+                }
+            }
+        }
+        ops
+    }
+
+    pub fn revert(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let options = self.list_options(cx);
+        self.reread(options, cx)
+    }
+
+    // Re-reads the directory in the new order. The order only changes once
+    // that worked, in sort_with_result().
+    pub fn set_sort(&mut self, sort: SortOrder, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let options = ListOptions { sort, ..self.list_options(cx) };
+        self.reread(options, cx)
+    }
+
+    fn reread(&mut self, options: ListOptions, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Re-reading directory...",
+            |ui_send, input_recv| async move {
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    // Marks and the cursor stay on the same files.
+    pub fn sort_with_result(&mut self, sort: SortOrder, result: OpenDirResult, cx: &mut Context<Self>) -> RefreshStats {
+        self.sort = sort;
+        let stats = self.revert_with_result(result, cx);
+        self.compile_search();
+        self.compile_filter();
+        stats
+    }
+
+    pub fn open_trash(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        if self.is_trash() {
+            return IOWorker::err("Already in the trash");
+        }
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Reading trash...",
+            |ui_send, input_recv| async move {
+                worker_blocking("the trash".to_string(), timeout, move || {
+                    Self::load_dir_result(PathBuf::from(TRASH_LOCATION), &options, None).map_err(|err| err.to_string())
+                }, &ui_send, &input_recv).await
+            })
+    }
+
+    pub fn restore(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        if !self.is_trash() {
+            return IOWorker::err("Restore only works in the trash");
+        }
+        let to_restore = self.operate_items();
+        if to_restore.is_empty() {
+            return IOWorker::err("Nothing to restore");
+        }
+
+        let to_restore: Vec<_> = to_restore.iter().map(|idx| self.trash[*idx].clone()).collect();
+        let path = self.dir_path.clone();
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Restoring...",
+            |ui_send, input_recv| async move {
+                let mut overwrite_response: Option<bool> = None;
+                let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
+
+                for info in to_restore {
+                    if worker_should_exit(&input_recv).await {
+                        break;
+                    }
+
+                    let original = info.original.display().to_string();
+                    if blocking_io({ let p = info.original.clone(); move || p.symlink_metadata() }).await.is_ok() {
+                        let should_overwrite = worker_multi_yes_no(
+                            format!("{} already exists. Replace it? The existing one will be moved to trash.", original).into(),
+                            &mut overwrite_response, &ui_send, &input_recv).await;
+                        if !should_overwrite {
+                            continue;
+                        }
+                        if let Err(err) = blocking_io({ let p = info.original.clone(); move || trash::move_to_trash(&p) }).await {
+                            worker_error(format!("Cannot move existing {} to trash. {}", original, err).into(), &ui_send, &input_recv).await;
+                            continue;
+                        }
+                    }
+
+                    worker_progress(format!("Restoring {}", original).into(), &mut last_progress_ts, &ui_send).await;
+
+                    if let Err(err) = Self::restore_entry(&info, &mut last_progress_ts, &ui_send, &input_recv).await {
+                        worker_error(format!("Cannot restore {}. {}", original, err).into(), &ui_send, &input_recv).await;
+                    }
+                }
+
+                Self::reload(path, options, None, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    // Puts a trashed item back where it came from. If that is on another
+    // filesystem, it is copied back and deleted, the way paste moves it.
+    async fn restore_entry(info: &TrashInfo, last_progress_ts: &mut SystemTime,
+                           ui_send: &Sender<DialogRequest>, input_recv: &Receiver<DialogResponse>) -> Result<(), String> {
+        match blocking_io({ let info = info.clone(); move || trash::restore(&info) }).await {
+            Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {}
+            result => return result.map_err(|err| err.to_string()),
+        }
+        let mut fail_set = BTreeSet::new();
+        Self::paste_entries(ui_send, input_recv, "", vec![(info.file_path.clone(), info.original.clone())], true,
+                            &mut fail_set, &mut Some(false), last_progress_ts).await;
+        Self::delete_dir_entries(ui_send, input_recv, "", vec![info.file_path.clone()],
+                                 &mut Some(true), &mut Some(true), last_progress_ts, &fail_set).await;
+        if blocking_io({ let p = info.file_path.clone(); move || p.symlink_metadata() }).await.is_ok() {
+            return Err("some items could not be moved back".to_string());
+        }
+        let info_path = info.info_path.clone();
+        blocking_io(move || std::fs::remove_file(info_path)).await.map_err(|err| err.to_string())
+    }
+
+    pub fn empty_trash(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Emptying trash...",
+            |ui_send, input_recv| async move {
+                let response = worker_dialog(
+                    DialogRequest::new("Permanently delete everything in the trash?".into(), DialogAction::yes_no()),
+                    &ui_send, &input_recv).await.unwrap();
+                if response.action != 0 {
+                    return Self::reload(path, options, current, timeout, &ui_send, &input_recv).await;
+                }
+
+                let mut file_response = Some(true);
+                let mut dir_response = Some(true);
+                let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
+                let exception_set = BTreeSet::new();
+
+                for trash_dir in trash::all_trash_dirs() {
+                    if worker_should_exit(&input_recv).await {
+                        break;
+                    }
+                    let Ok(to_delete) = Self::load_entry_as_paths(&trash_dir.files_dir()) else {
+                        continue;
+                    };
+                    Self::delete_dir_entries(
+                        &ui_send, &input_recv,
+                        "", to_delete,
+                        &mut file_response, &mut dir_response,
+                        &mut last_progress_ts,
+                        &exception_set).await;
+
+                    // Only drop the records whose files are really gone.
+                    let Ok(infos) = Self::load_entry_as_paths(&trash_dir.info_dir()) else {
+                        continue;
+                    };
+                    for info_path in infos {
+                        let Some(name) = info_path.file_stem() else {
+                            continue;
+                        };
+                        if trash_dir.files_dir().join(name).symlink_metadata().is_err() {
+                            let _ = std::fs::remove_file(&info_path);
+                        }
+                    }
+                }
+
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    // Copies or moves each source to its target path.
     async fn paste_entries(ui_send: &Sender<DialogRequest>, input_recv: &Receiver<DialogResponse>,
-                           path: &Path, prefix_dir: &str, to_paste: Vec<PathBuf>, should_move: bool,
+                           prefix_dir: &str, to_paste: Vec<(PathBuf, PathBuf)>, should_move: bool,
                            fail_set: &mut BTreeSet<PathBuf>, file_response: &mut Option<bool>,
                            last_progress_ts: &mut SystemTime) {
         let mut try_link = should_move;
-        for p in to_paste {
+        for (p, target) in to_paste {
             if worker_should_exit(input_recv).await {
                 break;
             }
 
-            let ent_name_osstring = p.file_name().unwrap();
-            let ent_name = prefix_dir.to_string() + ent_name_osstring.to_str().unwrap_or("");
-            let Ok(metadata) = p.symlink_metadata() else {
+            let ent_name = prefix_dir.to_string() + &entry::escape_name(p.file_name().unwrap());
+            let Ok(metadata) = blocking_io({ let p = p.clone(); move || p.symlink_metadata() }).await else {
                 fail_set.insert(p);
                 worker_error(format!("Cannot read metadata of {}", ent_name).into(), ui_send, input_recv).await;
                 continue;
             };
 
-            let mut target = path.to_path_buf();
-            target.push(ent_name_osstring);
-
             println!("target {}", target.display());
 
-            if target.exists() {
-                let Ok(target_metadata) = target.symlink_metadata() else {
+            // Whether the target exists, and its metadata if that is readable.
+            let (target_exists, target_metadata) = blocking_io({
+                let target = target.clone();
+                move || Ok((target.exists(), target.symlink_metadata()))
+            }).await.unwrap_or_else(|err| (false, Err(err)));
+            if target_exists {
+                let Ok(target_metadata) = target_metadata else {
                     fail_set.insert(p);
                     worker_error(format!("{} exists but cannot read its metadata", ent_name).into(), ui_send, input_recv).await;
                     continue;
@@ -699,29 +2185,41 @@ impl DirModel {
                             last_progress_ts, ui_send).await;
 
             if metadata.is_dir() {
-                if !target.exists() {
-                    if let Err(err) = std::fs::create_dir(&target) {
+                if !target_exists {
+                    if let Err(err) = blocking_io({ let target = target.clone(); move || std::fs::create_dir(target) }).await {
                         fail_set.insert(p);
                         worker_error(format!("Cannot create {}, {}", ent_name, err).into(), ui_send, input_recv).await;
                         continue;
                     }
                 }
-                let Ok(entries) = Self::load_entry_as_paths(&p) else {
+                let Ok(entries) = blocking_io({ let p = p.clone(); move || Self::load_entry_as_paths(&p) }).await else {
                     fail_set.insert(p);
                     worker_error(format!("Cannot read original dir {}", ent_name).into(), ui_send, input_recv).await;
                     continue;
                 };
                 let next_prefix_dir = ent_name.clone() + "/";
-                Box::pin(Self::paste_entries(ui_send, input_recv, &target, &next_prefix_dir, entries, should_move, fail_set, file_response, last_progress_ts)).await;
+                let entries = entries.into_iter().map(|e| {
+                    let target = target.join(e.file_name().unwrap());
+                    (e, target)
+                }).collect();
+                Box::pin(Self::paste_entries(ui_send, input_recv, &next_prefix_dir, entries, should_move, fail_set, file_response, last_progress_ts)).await;
             } else {
-                if try_link {
-                    if std::fs::hard_link(&p, &target).is_err() {
-                        try_link = false;
-                    } else {
-                        continue;
+                // Moving within a filesystem links and leaves the unlinking
+                // to delete_dir_entries().
+                let (linked, copied) = blocking_io({
+                    let (p, target) = (p.clone(), target.clone());
+                    move || {
+                        if try_link && std::fs::hard_link(&p, &target).is_ok() {
+                            return Ok((true, Ok(0)));
+                        }
+                        Ok((false, std::fs::copy(&p, &target)))
                     }
+                }).await.unwrap_or_else(|err| (false, Err(err)));
+                if linked {
+                    continue;
                 }
-                if let Err(err) = std::fs::copy(&p, &target) {
+                try_link = false;
+                if let Err(err) = copied {
                     fail_set.insert(p);
                     worker_error(format!("Cannot copy {}, {}", ent_name, err).into(), ui_send, input_recv).await;
                     continue;
@@ -731,11 +2229,22 @@ impl DirModel {
     }
 
     pub fn paste(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
-        let path = self.dir_path.clone();
-        let current = self.current.map(|cur| self.entries[cur].file_name().clone());
-        let show_hidden = self.show_hidden;
+        if self.is_trash() {
+            return IOWorker::err("Cannot paste into the trash");
+        }
         let to_paste = cx.global_mut::<AppGlobal>().take_stash();
         let should_move = cx.global::<AppGlobal>().is_stash_move();
+        self.paste_into(to_paste, self.dir_path.clone(), should_move, cx)
+    }
+
+    // Copies or moves to_paste into path, then re-reads dir_path.
+    fn paste_into(&mut self, to_paste: Vec<PathBuf>, path: PathBuf, should_move: bool,
+                  cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let dir_path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let journal = cx.global::<AppGlobal>().journal();
         return IOWorker::spawn(
             cx.background_executor(),
             "Pasting...",
@@ -744,8 +2253,15 @@ impl DirModel {
                 let mut fail_set = BTreeSet::new();
                 let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
 
+                // Only items that did not exist before can be undone.
+                let fresh: Vec<_> = to_paste.iter().filter_map(|p| {
+                    let target = path.join(p.file_name()?);
+                    target.symlink_metadata().is_err().then(|| (p.clone(), target))
+                }).collect();
+
+                let pairs = to_paste.iter().map(|p| (p.clone(), path.join(p.file_name().unwrap()))).collect();
                 Self::paste_entries(&ui_send, &input_recv,
-                                    &path, "", to_paste.clone(), should_move,
+                                    "", pairs, should_move,
                                     &mut fail_set,
                                     &mut file_response,
                                     &mut last_progress_ts).await;
@@ -766,19 +2282,75 @@ impl DirModel {
                                              &fail_set).await;
                 }
 
-                let entries = Self::load_entries(&path, show_hidden);
-                Ok(OpenDirResult {
-                    path,
-                    entries,
-                    current,
-                })
+                let ops = fresh.into_iter().filter(|(src, target)| {
+                    let done = if should_move { src.symlink_metadata().is_err() } else { !fail_set.contains(src) };
+                    done && target.symlink_metadata().is_ok()
+                }).map(|(src, target)| {
+                    JournalEntry::new(if should_move { OpKind::Move } else { OpKind::Copy }, src, target)
+                }).collect();
+                journal.record(ops);
+
+                Self::reload(dir_path, options, current, timeout, &ui_send, &input_recv).await
             });
     }
 
+    // What dragging entry idx carries: the marked items if it is one of
+    // them, otherwise just it.
+    pub fn drag_paths(&self, idx: usize) -> Vec<PathBuf> {
+        let items = if self.marked.contains(&idx) { self.operate_items() } else { vec![idx] };
+        items.iter().filter_map(|idx| self.entries.get(*idx)).map(|ent| ent.path.clone()).collect()
+    }
+
+    // Copies, moves or links paths dropped into dir, which is dir_path or a
+    // directory in it.
+    pub fn drop_paths(&mut self, paths: Vec<PathBuf>, dir: PathBuf, action: DropAction,
+                      cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        if self.is_trash() {
+            return IOWorker::err("Cannot drop into the trash");
+        }
+        if paths.iter().any(|p| dir.starts_with(p)) {
+            return IOWorker::err("Cannot drop a folder into itself");
+        }
+        match action {
+            DropAction::Copy => self.paste_into(paths, dir, false, cx),
+            DropAction::Move => self.paste_into(paths, dir, true, cx),
+            DropAction::Link => self.link_into(paths, dir, cx),
+        }
+    }
+
+    fn link_into(&mut self, paths: Vec<PathBuf>, dir: PathBuf, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let journal = cx.global::<AppGlobal>().journal();
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Linking...",
+            |ui_send, input_recv| async move {
+                let mut ops = vec![];
+                for p in paths {
+                    let Some(name) = p.file_name() else {
+                        continue;
+                    };
+                    let target = dir.join(name);
+                    match std::os::unix::fs::symlink(&p, &target) {
+                        Ok(()) => ops.push(JournalEntry::new(OpKind::Link, p, target)),
+                        Err(err) => worker_error(format!("Cannot link {}, {}", entry::escape_name(name), err).into(),
+                                                 &ui_send, &input_recv).await,
+                    }
+                }
+                journal.record(ops);
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    pub fn operate_paths(&self) -> Vec<PathBuf> {
+        self.operate_items().iter().map(|idx| self.entries[*idx].path.clone()).collect()
+    }
+
     pub fn copy_or_move(&mut self, cx: &mut Context<Self>, should_move: bool) {
-        let stash: Vec<_> = self.operate_items().iter().map(|idx| {
-            self.entries[*idx].path()
-        }).collect();
+        let stash = self.operate_paths();
         cx.global_mut::<AppGlobal>().stash(stash, should_move);
     }
 
@@ -786,32 +2358,210 @@ impl DirModel {
         let Some(cur) = self.current else {
             return IOWorker::err("Nothing selected.");
         };
-        let src = self.entries[cur].path();
-        let show_hidden = self.show_hidden;
+        if self.is_trash() {
+            return IOWorker::err("Cannot rename in the trash");
+        }
+        let src = self.entries[cur].path.clone();
+        // Names shown escaped are edited escaped.
+        let new_name = entry::unescape_name(&new_name);
+        // Find listings show, and so rename, paths relative to dir_path.
+        let target = match self.find {
+            Some(_) => self.dir_path.join(&new_name),
+            None => src.with_file_name(&new_name),
+        };
+        let mut options = self.list_options(cx);
+        if let Some(find) = options.find.as_mut() {
+            find.paths.iter_mut().filter(|p| **p == src).for_each(|p| *p = target.clone());
+        }
         let path = self.dir_path.clone();
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let journal = cx.global::<AppGlobal>().journal();
+        let what = entry::escape_name(src.as_os_str());
 
         IOWorker::spawn(
             cx.background_executor(),
             "Renaming",
             |ui_send, input_recv| async move {
-                let mut target = src.clone();
-                target.pop();
-                target.push(&new_name);
-
-                // We need to perform this in IOWorker because it may block on NFS.
-                if let Err(err) = std::fs::rename(&src, &target) {
+                // This may block on NFS.
+                let renamed = {
+                    let (src, target) = (src.clone(), target.clone());
+                    worker_blocking(what, timeout, move || {
+                        std::fs::rename(&src, &target).map_err(|err| err.to_string())
+                    }, &ui_send, &input_recv).await
+                };
+                if let Err(err) = renamed {
                     worker_error(
-                        format!("Cannot rename {}, {}", src.file_name().unwrap().to_string_lossy(), err).into(),
+                        format!("Cannot rename {}, {}", entry::escape_name(src.file_name().unwrap()), err).into(),
                         &ui_send,
                         &input_recv).await;
                     return Err("Rename failed".to_string());
                 }
-                let entries = Self::load_entries(&path, show_hidden);
-                Ok(OpenDirResult {
-                    path,
-                    entries,
-                    current: Some(OsString::from_str(&new_name).unwrap()),
-                })
+                journal.record(vec![JournalEntry::new(OpKind::Rename, src, target)]);
+                Self::reload(path, options, Some(new_name), timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    pub fn make_dir(&mut self, cx: &mut Context<Self>, name: String) -> Result<IOWorker<OpenDirResult>, String> {
+        if self.is_trash() {
+            return IOWorker::err("Cannot create folders in the trash");
+        }
+        if self.find.is_some() {
+            return IOWorker::err("Cannot create folders in a find listing");
+        }
+        if name.is_empty() || name.contains('/') {
+            return IOWorker::err("Invalid folder name");
+        }
+        let name = entry::unescape_name(&name);
+        let target = self.dir_path.join(&name);
+        let path = self.dir_path.clone();
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+        let what = entry::escape_name(target.as_os_str());
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Creating folder",
+            |ui_send, input_recv| async move {
+                let created = worker_blocking(what, timeout, move || {
+                    std::fs::create_dir(&target).map_err(|err| err.to_string())
+                }, &ui_send, &input_recv).await;
+                if let Err(err) = created {
+                    worker_error(format!("Cannot create {}, {}", entry::escape_name(&name), err).into(),
+                                 &ui_send, &input_recv).await;
+                    return Err("Creating folder failed".to_string());
+                }
+                Self::reload(path, options, Some(name), timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    // Runs a command from forg.toml through the shell, in dir_path, with
+    // the current or marked items as its arguments.
+    pub fn run_user_action(&mut self, index: usize, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let Some(action) = cx.global::<AppGlobal>().user_actions.get(index) else {
+            return IOWorker::err("No such action");
+        };
+        let (name, command) = (action.name.clone(), action.command.clone());
+        let paths = self.operate_paths();
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Running action...",
+            |ui_send, input_recv| async move {
+                let _ = ui_send.send(DialogRequest::new(format!("Running {}...", name).into(),
+                                                        vec![DialogAction::new("Cancel", "ctrl-g")])).await;
+                let output = Command::new("/bin/sh").arg("-c").arg(&command).arg("sh").args(&paths)
+                    .current_dir(&path)
+                    .stdin(Stdio::null())
+                    .kill_on_drop(true)
+                    .output();
+                let cancelled = async {
+                    let _ = input_recv.recv().await;
+                    None
+                };
+                match async { Some(output.await) }.or(cancelled).await {
+                    Some(Err(err)) => return Err(format!("Cannot run {}. {}", name, err)),
+                    Some(Ok(output)) if !output.status.success() => {
+                        let stderr = String::from_utf8_lossy(&output.stderr);
+                        let reason = stderr.lines().last().map_or(output.status.to_string(), |line| line.to_string());
+                        worker_error(format!("{} failed. {}", name, reason).into(), &ui_send, &input_recv).await;
+                    }
+                    // Cancelling kills the command.
+                    _ => {}
+                }
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
+            })
+    }
+
+    // What the properties dialog shows, all of it read by the loading
+    // worker.
+    pub fn properties(&self) -> Option<String> {
+        let ent = &self.entries[self.current?];
+        let mut lines = vec![
+            format!("Name: {}", ent.name),
+            format!("Location: {}", entry::escape_name(ent.path.parent().unwrap_or(Path::new("/")).as_os_str())),
+            format!("Type: {}", ent.mime),
+        ];
+        if !ent.is_dir() {
+            lines.push(format!("Size: {} ({} bytes)", entry::format_size(ent.size), ent.size));
+        }
+        lines.push(format!("Permissions: {} ({:o})", entry::format_mode(ent.file_type, &ent.permissions),
+                           ent.permissions.mode() & 0o7777));
+        if let Some(mtime) = ent.mtime {
+            lines.push(format!("Modified: {}", entry::format_mtime(mtime)));
+        }
+        if let Some(target) = &ent.symlink_target {
+            lines.push(format!("Target: {}", entry::escape_name(target.as_os_str())));
+        }
+        Some(lines.join("\n"))
+    }
+
+    pub fn undo(&mut self, cx: &mut Context<Self>) -> Result<IOWorker<OpenDirResult>, String> {
+        let Some(op) = cx.global::<AppGlobal>().journal().pop() else {
+            return IOWorker::err("Nothing to undo");
+        };
+        let path = self.dir_path.clone();
+        let current = self.current.map(|cur| self.entries[cur].os_name.clone());
+        let options = self.list_options(cx);
+        let timeout = cx.global::<AppGlobal>().io_timeout;
+
+        IOWorker::spawn(
+            cx.background_executor(),
+            "Undoing...",
+            |ui_send, input_recv| async move {
+                if let Err(err) = op.check() {
+                    // It would be refused every time, so it is dropped and the
+                    // next undo goes on to the older entries.
+                    worker_error(format!("Cannot undo {}. {}. Dropped it from the undo history.", op.describe(), err).into(),
+                                 &ui_send, &input_recv).await;
+                    return Self::reload(path, options, current, timeout, &ui_send, &input_recv).await;
+                }
+
+                let mut last_progress_ts = SystemTime::now() - Duration::from_millis(10);
+                let result = match op.kind {
+                    OpKind::Rename | OpKind::Move => {
+                        if std::fs::rename(&op.dst, &op.src).is_ok() {
+                            Ok(())
+                        } else {
+                            // Probably across devices. Move it back the way paste did.
+                            let mut fail_set = BTreeSet::new();
+                            let mut overwrite_response = Some(false);
+                            let mut file_response = Some(true);
+                            let mut dir_response = Some(true);
+                            Self::paste_entries(&ui_send, &input_recv, "", vec![(op.dst.clone(), op.src.clone())], true,
+                                                &mut fail_set, &mut overwrite_response, &mut last_progress_ts).await;
+                            Self::delete_dir_entries(&ui_send, &input_recv, "", vec![op.dst.clone()],
+                                                     &mut file_response, &mut dir_response,
+                                                     &mut last_progress_ts, &fail_set).await;
+                            if fail_set.is_empty() { Ok(()) } else { Err("some items could not be moved back".to_string()) }
+                        }
+                    },
+                    OpKind::Copy | OpKind::Link => {
+                        // Never unlink on undo, the copy or link goes to the trash.
+                        trash::move_to_trash(&op.dst).map(|_| ()).map_err(|err| err.to_string())
+                    },
+                    OpKind::Trash => match trash::info_path_for(&op.dst).filter(|p| p.symlink_metadata().is_ok()) {
+                        Some(info_path) => {
+                            let info = TrashInfo {
+                                info_path,
+                                file_path: op.dst.clone(),
+                                original: op.src.clone(),
+                                deletion_date: String::new(),
+                            };
+                            Self::restore_entry(&info, &mut last_progress_ts, &ui_send, &input_recv).await
+                        },
+                        None => Err(format!("{} has no .trashinfo", op.dst.display())),
+                    },
+                };
+
+                if let Err(err) = result {
+                    worker_error(format!("Cannot undo {}. {}", op.describe(), err).into(), &ui_send, &input_recv).await;
+                }
+
+                Self::reload(path, options, current, timeout, &ui_send, &input_recv).await
             })
     }
 }
@@ -0,0 +1,133 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::entry::FileEntry;
+use crate::sort;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SearchMode {
+    Prefix,
+    Substring,
+    IgnoreCase,
+    Regex,
+}
+
+impl SearchMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "prefix" => Some(Self::Prefix),
+            "substring" => Some(Self::Substring),
+            "ignore-case" => Some(Self::IgnoreCase),
+            "regex" => Some(Self::Regex),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Prefix => "prefix",
+            Self::Substring => "substring",
+            Self::IgnoreCase => "ignore-case",
+            Self::Regex => "regex",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Prefix => Self::Substring,
+            Self::Substring => Self::IgnoreCase,
+            Self::IgnoreCase => Self::Regex,
+            Self::Regex => Self::Prefix,
+        }
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        match self {
+            Self::Prefix => "Search: ",
+            Self::Substring => "Substring search: ",
+            Self::IgnoreCase => "Case-insensitive search: ",
+            Self::Regex => "Regex search: ",
+        }
+    }
+}
+
+// Short enough for the status bar. The full message spans several lines,
+// pointing at the error.
+pub fn regex_error(err: &regex::Error) -> String {
+    let err = err.to_string();
+    format!("Invalid regex: {}", err.lines().last().unwrap_or_default().trim_start_matches("error: "))
+}
+
+pub enum Matcher {
+    Prefix(String),
+    Substring(String),
+    // The query is folded and matched against FileEntry::folded.
+    Folded { query: String, prefix: bool },
+    Regex(Regex),
+}
+
+impl Matcher {
+    // natural makes prefix and substring searches match the way names are
+    // collated by the natural sort.
+    pub fn new(query: &str, mode: SearchMode, natural: bool) -> Result<Self, String> {
+        Ok(match mode {
+            SearchMode::Prefix if natural => Self::Folded { query: sort::fold_str(query), prefix: true },
+            SearchMode::Prefix => Self::Prefix(query.to_string()),
+            SearchMode::Substring if natural => Self::Folded { query: sort::fold_str(query), prefix: false },
+            SearchMode::Substring => Self::Substring(query.to_string()),
+            SearchMode::IgnoreCase => Self::Folded { query: sort::fold_str(query), prefix: false },
+            SearchMode::Regex => Self::Regex(Regex::new(query).map_err(|err| regex_error(&err))?),
+        })
+    }
+
+    pub fn is_match(&self, ent: &FileEntry) -> bool {
+        match self {
+            Self::Prefix(query) => ent.os_name.as_encoded_bytes().starts_with(query.as_bytes()) || ent.name.starts_with(query),
+            Self::Substring(query) => ent.name.contains(query),
+            Self::Folded { query, prefix: true } => ent.folded.starts_with(query),
+            Self::Folded { query, prefix: false } => ent.folded.contains(query),
+            Self::Regex(re) => re.is_match(&ent.name),
+        }
+    }
+
+    // Where in ent.name the match is, for highlighting. May be empty if the
+    // entry matched on bytes that its name shows escaped.
+    pub fn find(&self, ent: &FileEntry) -> Option<Range<usize>> {
+        if !self.is_match(ent) {
+            return None;
+        }
+        let found = match self {
+            Self::Prefix(query) => ent.name.starts_with(query).then_some(0..query.len()),
+            Self::Substring(query) => ent.name.find(query).map(|start| start..start + query.len()),
+            Self::Folded { query, prefix } => folded_range(&ent.name, query, *prefix),
+            Self::Regex(re) => re.find(&ent.name).map(|found| found.range()),
+        };
+        Some(found.unwrap_or(0..0))
+    }
+}
+
+// Finds query in the folded name and maps the match back to name, widening
+// it to whole characters.
+fn folded_range(name: &str, query: &str, prefix: bool) -> Option<Range<usize>> {
+    let mut folded = String::new();
+    // Where each character of name went in folded.
+    let mut spans = vec![];
+    for (idx, c) in name.char_indices() {
+        let start = folded.len();
+        folded.push_str(&sort::fold_str(c.encode_utf8(&mut [0; 4])));
+        spans.push((start..folded.len(), idx..idx + c.len_utf8()));
+    }
+    let start = if prefix { folded.starts_with(query).then_some(0)? } else { folded.find(query)? };
+    let end = start + query.len();
+    if query.is_empty() {
+        return Some(0..0);
+    }
+    // Combining marks fold to nothing, and stay with the letter before them.
+    let first = spans.iter().rposition(|(f, _)| f.start <= start && !f.is_empty())?;
+    let mut last = spans.iter().position(|(f, _)| f.end >= end && !f.is_empty())?;
+    while spans.get(last + 1).is_some_and(|(f, _)| f.is_empty()) {
+        last += 1;
+    }
+    Some(spans[first].1.start..spans[last].1.end)
+}
@@ -0,0 +1,150 @@
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use unicode_normalization::UnicodeNormalization;
+
+use crate::entry::FileEntry;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SortMode {
+    Name,
+    Size,
+    MTime,
+    Extension,
+    Type,
+}
+
+impl SortMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "name" => Some(Self::Name),
+            "size" => Some(Self::Size),
+            "mtime" => Some(Self::MTime),
+            "extension" => Some(Self::Extension),
+            "type" => Some(Self::Type),
+            _ => None,
+        }
+    }
+
+    pub fn to_str(&self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Size => "size",
+            Self::MTime => "mtime",
+            Self::Extension => "extension",
+            Self::Type => "type",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Name => Self::Size,
+            Self::Size => Self::MTime,
+            Self::MTime => Self::Extension,
+            Self::Extension => Self::Type,
+            Self::Type => Self::Name,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SortOrder {
+    pub mode: SortMode,
+    pub reverse: bool,
+    pub dirs_first: bool,
+    // Compare names case-insensitively, with digit runs as numbers.
+    pub natural: bool,
+}
+
+impl Default for SortOrder {
+    fn default() -> Self {
+        Self {
+            mode: SortMode::Name,
+            reverse: false,
+            dirs_first: true,
+            natural: false,
+        }
+    }
+}
+
+impl SortOrder {
+    pub fn describe(&self) -> String {
+        format!("by {}{}{}{}", self.mode.to_str(),
+                if self.reverse { " desc" } else { "" },
+                if self.natural { ", natural" } else { "" },
+                if self.dirs_first { "" } else { ", dirs mixed" })
+    }
+}
+
+// Compatibility-decomposed, without accents and lowercased, so "Ｆile",
+// "file", "FILE" and "fîle" all collate together.
+pub fn fold_name(name: &OsStr) -> String {
+    fold_str(&name.to_string_lossy())
+}
+
+pub fn fold_str(s: &str) -> String {
+    s.nfkd()
+        .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn digit_run(s: &[char], start: usize) -> usize {
+    s[start..].iter().take_while(|c| c.is_ascii_digit()).count()
+}
+
+// Like str::cmp, but runs of digits compare by numeric value: file2 < file10.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i].is_ascii_digit() && b[j].is_ascii_digit() {
+            let (na, nb) = (digit_run(&a, i), digit_run(&b, j));
+            let da: Vec<char> = a[i..i + na].iter().copied().skip_while(|c| *c == '0').collect();
+            let db: Vec<char> = b[j..j + nb].iter().copied().skip_while(|c| *c == '0').collect();
+            // Without leading zeros, a longer run is a bigger number. Equal
+            // values with more leading zeros go last.
+            let ord = da.len().cmp(&db.len()).then_with(|| da.cmp(&db)).then_with(|| na.cmp(&nb));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            i += na;
+            j += nb;
+        } else {
+            if a[i] != b[j] {
+                return a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j))
+}
+
+pub fn compare(p: &FileEntry, q: &FileEntry, order: &SortOrder) -> Ordering {
+    if order.dirs_first && p.is_dir() != q.is_dir() {
+        return if p.is_dir() { Ordering::Less } else { Ordering::Greater };
+    }
+    let extension = |ent: &FileEntry| match order.natural {
+        true => Path::new(&ent.folded).extension().map(|ext| ext.to_os_string()).unwrap_or_default(),
+        false => Path::new(&ent.os_name).extension().map(|ext| ext.to_os_string()).unwrap_or_default(),
+    };
+    let ord = match order.mode {
+        SortMode::Name => Ordering::Equal,
+        SortMode::Size => p.size.cmp(&q.size),
+        SortMode::MTime => p.mtime.cmp(&q.mtime),
+        SortMode::Extension => extension(p).cmp(&extension(q)),
+        SortMode::Type => p.mime.cmp(&q.mime),
+    }.then_with(|| match order.natural {
+        true => natural_cmp(&p.folded, &q.folded).then_with(|| p.os_name.cmp(&q.os_name)),
+        false => p.os_name.cmp(&q.os_name),
+    });
+
+    if order.reverse { ord.reverse() } else { ord }
+}
+
+pub fn sort_entries(entries: &mut [FileEntry], order: &SortOrder) {
+    entries.sort_by(|p, q| compare(p, q, order));
+}
@@ -0,0 +1,296 @@
+use std::ffi::{OsStr, OsString};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Implements the freedesktop.org Trash specification:
+// https://specifications.freedesktop.org/trash-spec/latest/
+
+pub struct TrashDir {
+    pub root: PathBuf,
+    // None for the home trash. For per-mount trash directories, this is the
+    // mount point and Path= in the .trashinfo is relative to it.
+    pub topdir: Option<PathBuf>,
+}
+
+impl TrashDir {
+    pub fn files_dir(&self) -> PathBuf {
+        self.root.join("files")
+    }
+
+    pub fn info_dir(&self) -> PathBuf {
+        self.root.join("info")
+    }
+
+    fn ensure_dirs(&self) -> io::Result<()> {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(self.files_dir())?;
+        builder.create(self.info_dir())
+    }
+}
+
+fn uid() -> u32 {
+    unsafe { libc::getuid() }
+}
+
+pub fn home_trash() -> TrashDir {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            let mut p = PathBuf::from(std::env::var_os("HOME").unwrap_or(OsString::from("/")));
+            p.push(".local/share");
+            p
+        });
+    TrashDir {
+        root: data_home.join("Trash"),
+        topdir: None,
+    }
+}
+
+fn mount_point(path: &Path) -> io::Result<PathBuf> {
+    let dev = path.metadata()?.dev();
+    let mut topdir = path.to_path_buf();
+    while let Some(parent) = topdir.parent() {
+        if parent.metadata()?.dev() != dev {
+            break;
+        }
+        topdir = parent.to_path_buf();
+    }
+    Ok(topdir)
+}
+
+fn topdir_trash(topdir: &Path) -> io::Result<TrashDir> {
+    // $topdir/.Trash is only usable if it is a real directory with the
+    // sticky bit set. Otherwise fall back to $topdir/.Trash-$uid.
+    let shared = topdir.join(".Trash");
+    if let Ok(metadata) = shared.symlink_metadata() {
+        if metadata.is_dir() && metadata.permissions().mode() & 0o1000 != 0 {
+            let trash = TrashDir {
+                root: shared.join(uid().to_string()),
+                topdir: Some(topdir.to_path_buf()),
+            };
+            if trash.ensure_dirs().is_ok() {
+                return Ok(trash);
+            }
+        }
+    }
+
+    let trash = TrashDir {
+        root: topdir.join(format!(".Trash-{}", uid())),
+        topdir: Some(topdir.to_path_buf()),
+    };
+    trash.ensure_dirs()?;
+    Ok(trash)
+}
+
+pub fn trash_dir_for(path: &Path) -> io::Result<TrashDir> {
+    let dev = path.symlink_metadata()?.dev();
+    let home = home_trash();
+    home.ensure_dirs()?;
+    if home.root.metadata()?.dev() == dev {
+        return Ok(home);
+    }
+
+    let parent = path.parent().ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+    topdir_trash(&mount_point(parent)?)
+}
+
+fn absolute_path(path: &Path) -> io::Result<PathBuf> {
+    // Do not canonicalize the item itself, it might be a symlink.
+    let parent = path.parent().ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+    let name = path.file_name().ok_or(io::Error::from(io::ErrorKind::InvalidInput))?;
+    Ok(parent.canonicalize()?.join(name))
+}
+
+pub fn percent_encode(path: &OsStr) -> String {
+    let mut encoded = String::new();
+    for b in path.as_bytes() {
+        if b.is_ascii_alphanumeric() || b"/-_.~".contains(b) {
+            encoded.push(*b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+fn deletion_date(time: SystemTime) -> String {
+    let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe { libc::localtime_r(&secs, &mut tm) };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday, tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+// Moves path into the trash and returns where it ended up under files/.
+pub fn move_to_trash(path: &Path) -> io::Result<PathBuf> {
+    let path = absolute_path(path)?;
+    let trash = trash_dir_for(&path)?;
+    let info_path_field = match &trash.topdir {
+        Some(topdir) => path.strip_prefix(topdir).unwrap_or(&path).as_os_str().to_os_string(),
+        None => path.as_os_str().to_os_string(),
+    };
+    let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n",
+                       percent_encode(&info_path_field), deletion_date(SystemTime::now()));
+
+    let name = path.file_name().unwrap().to_os_string();
+    let mut suffix = 1;
+    loop {
+        let mut trash_name = name.clone();
+        if suffix > 1 {
+            trash_name.push(format!(".{}", suffix));
+        }
+        let mut info_name = trash_name.clone();
+        info_name.push(".trashinfo");
+        let info_path = trash.info_dir().join(&info_name);
+        let target = trash.files_dir().join(&trash_name);
+
+        // Creating the .trashinfo with O_EXCL reserves the name.
+        let mut info_file = match OpenOptions::new().write(true).create_new(true).open(&info_path) {
+            Ok(f) => f,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                suffix += 1;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        if target.symlink_metadata().is_ok() {
+            // Stale entry without its .trashinfo. Leave it alone.
+            let _ = std::fs::remove_file(&info_path);
+            suffix += 1;
+            continue;
+        }
+
+        if let Err(err) = info_file.write_all(info.as_bytes()).and_then(|_| std::fs::rename(&path, &target)) {
+            let _ = std::fs::remove_file(&info_path);
+            return Err(err);
+        }
+        return Ok(target);
+    }
+}
+
+pub const TRASH_LOCATION: &str = "trash:";
+
+#[derive(Clone)]
+pub struct TrashInfo {
+    pub file_path: PathBuf,
+    pub info_path: PathBuf,
+    pub original: PathBuf,
+    pub deletion_date: String,
+}
+
+fn percent_decode(s: &str) -> OsString {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    OsString::from_vec(decoded)
+}
+
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = std::fs::read_to_string("/proc/self/mounts") else {
+        return vec![];
+    };
+    // Mount points are the second field, with whitespace escaped as octal.
+    mounts.lines().filter_map(|line| line.split(' ').nth(1)).map(|field| {
+        let field = field.replace("\\040", " ").replace("\\011", "\t").replace("\\012", "\n").replace("\\134", "\\");
+        PathBuf::from(field)
+    }).collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    vec![]
+}
+
+pub fn all_trash_dirs() -> Vec<TrashDir> {
+    let mut dirs = vec![home_trash()];
+    for topdir in mount_points() {
+        for root in [topdir.join(".Trash").join(uid().to_string()), topdir.join(format!(".Trash-{}", uid()))] {
+            if root.join("info").is_dir() && !dirs.iter().any(|d: &TrashDir| d.root == root) {
+                dirs.push(TrashDir { root, topdir: Some(topdir.clone()) });
+            }
+        }
+    }
+    dirs
+}
+
+fn parse_trash_info(trash: &TrashDir, info_path: PathBuf) -> Option<TrashInfo> {
+    let content = std::fs::read_to_string(&info_path).ok()?;
+    let mut lines = content.lines().map(|l| l.trim());
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+    let mut original = None;
+    let mut deletion_date = String::new();
+    for line in lines {
+        if let Some(path) = line.strip_prefix("Path=") {
+            let path = PathBuf::from(percent_decode(path));
+            original = Some(match &trash.topdir {
+                Some(topdir) if path.is_relative() => topdir.join(path),
+                _ => path,
+            });
+        } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+            deletion_date = date.replace('T', " ");
+        }
+    }
+    let name = info_path.file_stem()?.to_os_string();
+    Some(TrashInfo {
+        file_path: trash.files_dir().join(name),
+        info_path,
+        original: original?,
+        deletion_date,
+    })
+}
+
+// Lists everything in all trash directories, most recently deleted first.
+pub fn list_trash() -> Vec<TrashInfo> {
+    let mut infos = vec![];
+    for trash in all_trash_dirs() {
+        let Ok(entries) = std::fs::read_dir(trash.info_dir()) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "trashinfo") {
+                if let Some(info) = parse_trash_info(&trash, path) {
+                    infos.push(info);
+                }
+            }
+        }
+    }
+    infos.sort_by(|p, q| q.deletion_date.cmp(&p.deletion_date));
+    infos
+}
+
+// The .trashinfo record belonging to an entry under files/.
+pub fn info_path_for(file_path: &Path) -> Option<PathBuf> {
+    let mut info_name = file_path.file_name()?.to_os_string();
+    info_name.push(".trashinfo");
+    Some(file_path.parent()?.parent()?.join("info").join(info_name))
+}
+
+pub fn restore(info: &TrashInfo) -> io::Result<()> {
+    if let Some(parent) = info.original.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::rename(&info.file_path, &info.original)?;
+    std::fs::remove_file(&info.info_path)
+}
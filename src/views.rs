@@ -1,10 +1,18 @@
 use gpui::*;
-use std::fs::DirEntry;
+use std::cell::OnceCell;
+use std::collections::BTreeSet;
 use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
 
 use crate::app_global::AppGlobal;
-use crate::line_edit::{CommitEvent};
-use crate::models::{DialogRequest, DialogResponse, IOWorker, OpenDirResult};
+use crate::context_menu::ContextMenu;
+use crate::entry::{self, FileEntry};
+use crate::line_edit::{ChangeEvent, CommitEvent};
+use crate::models::{DialogRequest, DialogResponse, DropAction, IOWorker, LoadFailedEvent, OpenDirResult};
+use crate::search::SearchMode;
+use crate::sort::SortOrder;
 use super::line_edit::LineEdit;
 use super::models::DirModel;
 use super::dialog::Dialog;
@@ -14,7 +22,6 @@ struct DirEntryView {
     id: usize,
     listview: Entity<FileListView>,
     icon: ImageSource,
-    // mime_type: String,
     model: Entity<DirModel>,
     text_offset: f32,
 }
@@ -24,7 +31,6 @@ impl DirEntryView {
         id: usize,
         icon: ImageSource,
         listview: Entity<FileListView>,
-        _mime_type: String,
         model: Entity<DirModel>,
         text_offset: f32,
     ) -> Self {
@@ -32,7 +38,6 @@ impl DirEntryView {
             id,
             listview,
             icon,
-            // mime_type,
             model,
             text_offset,
         }
@@ -41,22 +46,72 @@ impl DirEntryView {
 
 static FILENAME_FALLBACK: &str = "Unrecognizable Unicode";
 
+// What dragging an item carries. The paths are read off the model once,
+// when the drag starts, so rendering does not copy the marked paths for
+// every item and changes to the listing during the drag do not change
+// what is dropped.
+#[derive(Clone)]
+struct DraggedEntries {
+    model: Entity<DirModel>,
+    id: usize,
+    paths: Rc<OnceCell<Vec<PathBuf>>>,
+}
+
+// Follows the pointer while dragging.
+struct DragPreview {
+    icon: ImageSource,
+    label: SharedString,
+}
+
+impl Render for DragPreview {
+    fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
+        div()
+            .flex()
+            .flex_row()
+            .items_center()
+            .gap_1()
+            .px_2()
+            .py_1()
+            .bg(rgb(0xffffff))
+            .border_1()
+            .border_color(rgb(0x0068d9))
+            .text_size(px(12.))
+            .child(img(self.icon.clone()).w(px(32.)).h(px(32.)))
+            .child(self.label.clone())
+    }
+}
+
 impl RenderOnce for DirEntryView {
     fn render(self, _window: &mut Window, cx: &mut App) -> impl IntoElement {
         let model = self.model.read(cx);
-        let text = model.entries[self.id].file_name().into_string().unwrap_or(FILENAME_FALLBACK.to_string());
+        let mut text = model.label(self.id).unwrap_or(FILENAME_FALLBACK.to_string());
         let listview = self.listview.read(cx);
         let text_radius = listview.text_radius();
         let icon_size = listview.icon_size.clone();
         let margin_size = listview.margin_size();
         let font_size = listview.font_size();
 
+        if model.current == Some(self.id) {
+            if let Some(detail) = model.detail(self.id) {
+                text = text + "\n" + &detail;
+            }
+        }
+
+        // Highlight what the search matched.
+        let highlight = HighlightStyle {
+            background_color: Some(rgb(0xffb340).into()),
+            color: Some(rgb(0x1a1a1a).into()),
+            ..Default::default()
+        };
+        let label = StyledText::new(text.clone())
+            .with_highlights(model.search_match_range(self.id).map(|range| (range, highlight)));
+
         let mut label_div = div()
             .flex_none()
             .px(px(self.text_offset))
             .text_size(px(font_size))
             .rounded(px(text_radius))
-            .child(text.clone());
+            .child(label);
 
         if model.current == Some(self.id) {
             label_div = label_div.bg(rgb(0x0068d9)).absolute().top(px(icon_size)).text_color(rgb(0xf0f0f0));
@@ -64,14 +119,12 @@ impl RenderOnce for DirEntryView {
             label_div = label_div.whitespace_nowrap().overflow_x_hidden().h(px(font_size + 2. * text_radius));
         }
 
-        if let Ok(file_type) = model.entries[self.id].file_type() {
-            if file_type.is_symlink() {
-                label_div
-                    .style()
-                    .text_style()
-                    .get_or_insert_with(Default::default)
-                    .color = Some(rgb(0x47c8d6).into());
-            }
+        if model.entries.get(self.id).is_some_and(FileEntry::is_symlink) {
+            label_div
+                .style()
+                .text_style()
+                .get_or_insert_with(Default::default)
+                .color = Some(rgb(0x47c8d6).into());
         }
 
         let mut item_div = div()
@@ -92,8 +145,54 @@ impl RenderOnce for DirEntryView {
         if model.marked.contains(&self.id) {
             item_div.style().background = Some(Fill::from(rgb(0xfff7a0)));
         }
+        // Flags win over marks, as x would delete the item either way.
+        if model.flagged.contains(&self.id) {
+            item_div.style().background = Some(Fill::from(rgb(0xffb0a8)));
+        }
 
+        let id = self.id;
+        // Folders take drops, from this window, other windows and other apps.
+        // They are known by path, as the listing may change before the drop.
+        if let Some(dir) = model.entries.get(id).filter(|ent| ent.is_dir()).map(|ent| ent.path.clone()) {
+            let drop_view = self.listview.clone();
+            let external_view = self.listview.clone();
+            let external_dir = dir.clone();
+            item_div = item_div
+                .drag_over::<DraggedEntries>(|style, _, _, _| style.bg(rgb(0xb3d7ff)))
+                .drag_over::<ExternalPaths>(|style, _, _, _| style.bg(rgb(0xb3d7ff)))
+                .on_drop(move |dragged: &DraggedEntries, window, cx| {
+                    drop_view.update(cx, |this, cx| this.on_drop_entries(dragged, Some(dir.clone()), window, cx));
+                })
+                .on_drop(move |paths: &ExternalPaths, window, cx| {
+                    external_view.update(cx, |this, cx| {
+                        this.on_drop_paths(paths.paths().to_vec(), Some(external_dir.clone()), window, cx)
+                    });
+                });
+        }
+
+        let view = self.listview.clone();
+        let middle_view = self.listview.clone();
+        let menu_view = self.listview.clone();
+        let icon = self.icon.clone();
         item_div
+            .on_drag(DraggedEntries { model: self.model.clone(), id, paths: Rc::default() }, move |dragged, _, _, cx| {
+                let paths = dragged.paths.get_or_init(|| dragged.model.read(cx).drag_paths(dragged.id));
+                let label = match paths.as_slice() {
+                    [path] => entry::escape_name(path.file_name().unwrap_or_default()),
+                    _ => format!("{} items", paths.len()),
+                };
+                cx.new(|_| DragPreview { icon: icon.clone(), label: label.into() })
+            })
+            .on_click(move |event, window, cx| {
+                view.update(cx, |this, cx| this.on_item_click(id, event, window, cx));
+            })
+            .on_mouse_down(MouseButton::Middle, move |_, window, cx| {
+                middle_view.update(cx, |this, cx| this.on_item_middle_click(id, window, cx));
+            })
+            .on_mouse_down(MouseButton::Right, move |event, window, cx| {
+                cx.stop_propagation();
+                menu_view.update(cx, |this, cx| this.on_item_menu(id, event.position, window, cx));
+            })
     }
 }
 
@@ -105,7 +204,7 @@ enum ZoomAction {
 
 #[derive(Clone, PartialEq, serde_derive::Deserialize, schemars::JsonSchema, Action)]
 enum MoveAction {
-    Next, Prev, Home, End,
+    Next, Prev, Home, End, Up, Down, Left, Right, PageUp, PageDown,
 }
 
 #[derive(Clone, PartialEq, serde_derive::Deserialize, schemars::JsonSchema, Action)]
@@ -113,11 +212,21 @@ struct CopyOrCut {
     should_move: bool
 }
 
+// One of the actions from forg.toml.
+#[derive(Clone, PartialEq, serde_derive::Deserialize, schemars::JsonSchema, Action)]
+struct RunUserAction {
+    index: usize
+}
+
 actions!(
     actions,
     [
-        ToggleMark, ToggleHidden, Open, Remove, Paste, Rename, Up, Back, Search, Escape,
-        NewWindow, CloseWindow
+        ToggleMark, Unmark, MarkRegex, MarkExtension, MarkDirs, MarkSymlinks, MarkExecutables, InvertMarks,
+        UnmarkAll, Flag, FlagBackups, FlagAutosaves, ExecuteFlagged, ToggleHidden, Open, Trash, Remove, Paste, Rename, Up, Back, Search, SearchBackward,
+        SearchUndo, CycleSearchMode, Filter, FindName, FindGrep, Escape,
+        OpenTrash, Restore, EmptyTrash, Undo, Revert, CycleSort, ReverseSort, ToggleDirsFirst,
+        ToggleNaturalSort,
+        NewWindow, CloseWindow, OpenWith, Properties, NewFolder, CopyUris
     ]
 );
 
@@ -125,6 +234,12 @@ actions!(
 pub enum StatusPrompt {
     Search,
     Rename,
+    Filter,
+    Find,
+    Grep,
+    MarkRegex,
+    MarkExtension,
+    NewFolder,
 }
 
 impl StatusPrompt {
@@ -132,10 +247,35 @@ impl StatusPrompt {
         match self {
             Self::Search => "Search: ",
             Self::Rename => "Rename: ",
+            Self::Filter => "Filter: ",
+            Self::Find => "Find name: ",
+            Self::Grep => "Grep regex: ",
+            Self::MarkRegex => "Mark regex: ",
+            Self::MarkExtension => "Mark extension: ",
+            Self::NewFolder => "New folder: ",
         }
     }
 }
 
+// A selection rectangle being dragged out in the list.
+struct RubberBand {
+    // Corners in content coordinates, so they stay put while scrolling.
+    start: Point<Pixels>,
+    end: Point<Pixels>,
+    // Where the pointer last was, in window coordinates.
+    pointer: Point<Pixels>,
+    // The marks the band adds to.
+    base: BTreeSet<usize>,
+    // Scrolls while the pointer is held at the top or bottom edge.
+    _autoscroll: Task<()>,
+}
+
+// How close to the edge the pointer scrolls the list, and how far each
+// step goes.
+const AUTOSCROLL_EDGE: f32 = 24.;
+const AUTOSCROLL_STEP: f32 = 16.;
+const AUTOSCROLL_INTERVAL: Duration = Duration::from_millis(30);
+
 pub struct FileListView {
     model: Entity<DirModel>,
     scroll_handle: UniformListScrollHandle,
@@ -149,15 +289,27 @@ pub struct FileListView {
     pub line_edit: Entity<LineEdit>,
     status_text: SharedString,
     status_prompt: Option<StatusPrompt>,
+    status_note: Option<String>,
 
     focus_handle: FocusHandle,
     scroll_range: Range<usize>,
+    rubber_band: Option<RubberBand>,
+    context_menu: Option<Entity<ContextMenu>>,
 }
 
 impl FileListView {
     fn on_dismiss<V>(&mut self, _source: &Entity<V>, _: &DismissEvent, window: &mut Window, cx: &mut Context<Self>) {
         println!("dismiss event reset");
+        self.close_prompt(window, cx);
         self.focus_handle.focus(window);
+        Self::enter_mode(cx);
+    }
+    // Drops whatever was being typed in the status bar.
+    fn close_prompt(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        if self.status_prompt == Some(StatusPrompt::Filter) {
+            // Cancelled while typing the filter.
+            self.model.update(cx, &DirModel::clear_filter);
+        }
         self.line_edit.update(cx, |view, _| {
             view.reset();
         });
@@ -165,7 +317,6 @@ impl FileListView {
             view.model.update(cx, &DirModel::search_clear);
             view.reset_status(cx);
         });
-        Self::enter_mode(cx);
     }
     fn enter_mode(cx: &mut App) {
         cx.clear_key_bindings();
@@ -174,26 +325,73 @@ impl FileListView {
             KeyBinding::new("p", MoveAction::Prev, None),
             KeyBinding::new(if cfg!(target_os = "macos") { "cmd-<" } else { "alt-<" }, MoveAction::Home, None),
             KeyBinding::new(if cfg!(target_os = "macos") { "cmd->" } else { "alt->" }, MoveAction::End, None),
+            KeyBinding::new("ctrl-n", MoveAction::Down, None),
+            KeyBinding::new("ctrl-p", MoveAction::Up, None),
+            KeyBinding::new("down", MoveAction::Down, None),
+            KeyBinding::new("up", MoveAction::Up, None),
+            KeyBinding::new("left", MoveAction::Left, None),
+            KeyBinding::new("right", MoveAction::Right, None),
+            KeyBinding::new("ctrl-v", MoveAction::PageDown, None),
+            KeyBinding::new("alt-v", MoveAction::PageUp, None),
+            KeyBinding::new("pagedown", MoveAction::PageDown, None),
+            KeyBinding::new("pageup", MoveAction::PageUp, None),
             KeyBinding::new("m", ToggleMark, None),
+            KeyBinding::new("u", Unmark, None),
+            KeyBinding::new("shift-u", UnmarkAll, None),
+            KeyBinding::new("t", InvertMarks, None),
+            KeyBinding::new("% m", MarkRegex, None),
+            KeyBinding::new("* .", MarkExtension, None),
+            KeyBinding::new("* /", MarkDirs, None),
+            KeyBinding::new("* @", MarkSymlinks, None),
+            KeyBinding::new("* *", MarkExecutables, None),
             KeyBinding::new("h", ToggleHidden, None),
-            KeyBinding::new("d", Remove, None),
+            KeyBinding::new("d", Flag, None),
+            KeyBinding::new("~", FlagBackups, None),
+            KeyBinding::new("#", FlagAutosaves, None),
+            KeyBinding::new("x", ExecuteFlagged, None),
+            KeyBinding::new("delete", Trash, None),
+            KeyBinding::new("shift-d", Remove, None),
             KeyBinding::new("r", Rename, None),
+            KeyBinding::new("+", NewFolder, None),
+            KeyBinding::new("alt-enter", Properties, None),
+            KeyBinding::new("g", Revert, None),
+            KeyBinding::new("s", CycleSort, None),
+            KeyBinding::new("shift-s", ReverseSort, None),
+            KeyBinding::new("alt-s", ToggleDirsFirst, None),
+            KeyBinding::new("ctrl-x n", ToggleNaturalSort, None),
             KeyBinding::new("enter", Open, None),
             KeyBinding::new("backspace", Back, None),
             KeyBinding::new("^", Up, None),
             KeyBinding::new("ctrl-s", Search, None),
+            KeyBinding::new("ctrl-r", SearchBackward, None),
+            KeyBinding::new("ctrl-x s", CycleSearchMode, None),
+            KeyBinding::new("/", Filter, None),
+            KeyBinding::new("ctrl-c ctrl-f", Filter, None),
+            KeyBinding::new("shift-f", FindName, None),
+            KeyBinding::new("shift-g", FindGrep, None),
             KeyBinding::new("escape", Escape, None),
             KeyBinding::new("ctrl-g", Escape, None),
             KeyBinding::new("ctrl-w", CopyOrCut { should_move: true }, None),
             KeyBinding::new("alt-w", CopyOrCut { should_move: false }, None),
             KeyBinding::new("ctrl-y", Paste, None),
+            KeyBinding::new("ctrl-c u", CopyUris, None),
             KeyBinding::new("shift-n", NewWindow, None),
             KeyBinding::new("ctrl-x k", CloseWindow, None),
+            KeyBinding::new("ctrl-x t", OpenTrash, None),
+            KeyBinding::new("shift-r", Restore, None),
+            KeyBinding::new("ctrl-x shift-t", EmptyTrash, None),
+            KeyBinding::new("ctrl-/", Undo, None),
+            KeyBinding::new("ctrl-_", Undo, None),
+            KeyBinding::new("ctrl-x u", Undo, None),
 
             KeyBinding::new("ctrl-=", ZoomAction::In, None),
             KeyBinding::new("ctrl--", ZoomAction::Out, None),
             KeyBinding::new("ctrl-0", ZoomAction::Reset, None),
         ]);
+        let user_keys: Vec<_> = cx.global::<AppGlobal>().user_actions.iter().enumerate()
+            .filter_map(|(index, action)| action.key.as_ref().map(|key| KeyBinding::new(key, RunUserAction { index }, None)))
+            .collect();
+        cx.bind_keys(user_keys);
     }
 
     fn on_line_edit_commit(&mut self, edit: &Entity<LineEdit>, _: &CommitEvent, window: &mut Window, cx: &mut Context<Self>) {
@@ -204,22 +402,47 @@ impl FileListView {
             return;
         };
 
-        if *prompt == StatusPrompt::Search {
-            self.update_view(window, cx, |view, _window, cx| {
-                let result = view.model.update(cx, |model, cx| {
-                    model.start_with = edit.read(cx).content.to_string();
-                    model.search_next(cx)
-                });
-                if result {
-                    view.status_text = SharedString::from(format!(
-                        "Found at Location {}",
-                        view.model.read(cx).current.unwrap()
-                    ));
-                } else {
-                    view.status_text = SharedString::from("Not Found");
+        // Search and filter already took effect while typing.
+        if *prompt == StatusPrompt::Filter {
+            self.line_edit.update(cx, |view, _| view.reset());
+            self.reset_status(cx);
+        } else if *prompt == StatusPrompt::Find {
+            let pattern = edit.read(cx).content.to_string();
+            let regex = self.model.read(cx).search_mode == SearchMode::Regex;
+            match self.model.update(cx, |model, cx| model.find_name(pattern, regex, cx)) {
+                Ok(()) => self.on_navigate(window, cx),
+                Err(err) => {
+                    self.status_note = Some(err);
+                    self.line_edit.update(cx, |_, cx| cx.emit(DismissEvent));
                 }
-
+            }
+        } else if *prompt == StatusPrompt::Grep {
+            let pattern = edit.read(cx).content.to_string();
+            match self.model.update(cx, |model, cx| model.find_grep(pattern, cx)) {
+                Ok(()) => self.on_navigate(window, cx),
+                Err(err) => {
+                    self.status_note = Some(err);
+                    self.line_edit.update(cx, |_, cx| cx.emit(DismissEvent));
+                }
+            }
+        } else if *prompt == StatusPrompt::MarkRegex {
+            let pattern = edit.read(cx).content.to_string();
+            let marked = self.model.update(cx, |model, cx| model.mark_regex(&pattern, cx));
+            self.status_note = Some(match marked {
+                Ok(marked) => format!("{} marked", marked),
+                Err(err) => err,
             });
+            self.line_edit.update(cx, |_, cx| cx.emit(DismissEvent));
+        } else if *prompt == StatusPrompt::MarkExtension {
+            let ext = edit.read(cx).content.to_string();
+            let marked = self.model.update(cx, |model, cx| model.mark_extension(&ext, cx));
+            self.status_note = Some(format!("{} marked", marked));
+            self.line_edit.update(cx, |_, cx| cx.emit(DismissEvent));
+        } else if *prompt == StatusPrompt::NewFolder {
+            let name = edit.read(cx).content.to_string();
+            self.reset_status(cx);
+            let worker = self.model.update(cx, |model, cx| model.make_dir(cx, name));
+            self.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
         } else if *prompt == StatusPrompt::Rename {
             let new_name = edit.read(cx).content.to_string();
             self.reset_status(cx);
@@ -228,6 +451,72 @@ impl FileListView {
         }
     }
 
+    fn on_line_edit_change(&mut self, edit: &Entity<LineEdit>, _: &ChangeEvent, window: &mut Window, cx: &mut Context<Self>) {
+        let query = edit.read(cx).content.to_string();
+        match self.status_prompt {
+            Some(StatusPrompt::Search) => {
+                let found = self.model.update(cx, |model, cx| model.search_update(query, cx));
+                self.show_search_result(found, cx);
+            }
+            Some(StatusPrompt::Filter) => {
+                let shown = self.model.update(cx, |model, cx| model.set_filter(query, cx));
+                self.show_filter_result(shown, cx);
+            }
+            _ => return,
+        }
+        // Scrolls to the match.
+        self.update_view(window, cx, |_, _, _| {});
+    }
+
+    fn show_search_result(&mut self, found: Result<bool, String>, cx: &mut Context<Self>) {
+        self.status_text = match (found, self.model.read(cx).current_pos()) {
+            (Ok(true), Some(pos)) => SharedString::from(format!("Found at Location {}", pos)),
+            (Ok(_), _) => SharedString::from("Not Found"),
+            (Err(err), _) => SharedString::from(err),
+        };
+    }
+
+    fn show_filter_result(&mut self, shown: Result<usize, String>, cx: &mut Context<Self>) {
+        self.status_text = match shown {
+            Ok(shown) => SharedString::from(format!("{}/{} Items", shown, self.model.read(cx).entries.len())),
+            Err(err) => SharedString::from(err),
+        };
+    }
+
+    fn on_cycle_search_mode(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let mode = self.model.read(cx).search_mode.next();
+        self.model.update(cx, |model, cx| model.set_search_mode(mode, cx));
+        let editing = self.line_edit.focus_handle(cx).is_focused(window);
+        if self.isearch_active(window, cx) {
+            let query = self.line_edit.read(cx).content.to_string();
+            let found = self.model.update(cx, |model, cx| model.search_update(query, cx));
+            self.show_search_result(found, cx);
+        } else if editing && self.status_prompt == Some(StatusPrompt::Filter) {
+            let query = self.line_edit.read(cx).content.to_string();
+            let shown = self.model.update(cx, |model, cx| model.set_filter(query, cx));
+            self.show_filter_result(shown, cx);
+        } else if !editing {
+            self.status_note = Some(format!("{} search", mode.to_str()));
+            self.reset_status(cx);
+        }
+    }
+
+    fn note_count(&mut self, count: usize, what: &str, cx: &mut Context<Self>) {
+        self.status_note = Some(format!("{} {}", count, what));
+        self.reset_status(cx);
+        cx.notify();
+    }
+
+    // A prompt is being typed into. A committed search leaves its prompt
+    // showing without the focus.
+    fn editing(&self, window: &Window, cx: &App) -> bool {
+        self.line_edit.focus_handle(cx).is_focused(window)
+    }
+
+    fn isearch_active(&self, window: &Window, cx: &App) -> bool {
+        self.status_prompt == Some(StatusPrompt::Search) && self.editing(window, cx)
+    }
+
     pub fn new(window: &mut Window, cx: &mut Context<Self>, model: Entity<DirModel>) -> Self {
         let focus_handle = cx.focus_handle();
 
@@ -242,6 +531,35 @@ impl FileListView {
         cx.subscribe_in(&dialog, window, Self::on_dismiss).detach();
 
         cx.subscribe_in(&line_edit, window, Self::on_line_edit_commit).detach();
+        cx.subscribe_in(&line_edit, window, Self::on_line_edit_change).detach();
+        // Runs after the line edit has bound its own keys.
+        cx.on_focus(&line_edit.focus_handle(cx), window, |this, _window, cx| {
+            if this.status_prompt == Some(StatusPrompt::Search) {
+                cx.bind_keys([
+                    KeyBinding::new("ctrl-s", Search, None),
+                    KeyBinding::new("ctrl-r", SearchBackward, None),
+                    KeyBinding::new("backspace", SearchUndo, None),
+                ]);
+            }
+            if matches!(this.status_prompt, Some(StatusPrompt::Search | StatusPrompt::Filter | StatusPrompt::Find)) {
+                cx.bind_keys([KeyBinding::new("ctrl-x s", CycleSearchMode, None)]);
+            }
+        }).detach();
+        cx.subscribe_in(&model, window, |this, _model, event: &LoadFailedEvent, window, cx| {
+            // Not on_navigate(): the line edit's DismissEvent would be handled
+            // after the dialog shows, and take its focus and keys away.
+            this.show_location(window, cx);
+            this.close_prompt(window, cx);
+            this.dialog.update(cx, |dialog, cx| dialog.show_just_error(event.0.to_string().into(), window, cx));
+        }).detach();
+        // The model notifies when the directory changed underneath us.
+        cx.observe_in(&model, window, |this, _model, window, cx| {
+            this.clear_text_offset_cache(window, cx);
+            if this.status_prompt.is_none() {
+                this.reset_status(cx);
+            }
+            cx.notify();
+        }).detach();
 
         Self {
             model,
@@ -254,10 +572,226 @@ impl FileListView {
             line_edit,
             status_text: "".into(),
             status_prompt: None,
+            status_note: None,
             focus_handle,
+            rubber_band: None,
+            context_menu: None,
         }
     }
 
+    fn show_context_menu(&mut self, menu: ContextMenu, cx: &mut Context<Self>) {
+        let menu = cx.new(|_| menu);
+        cx.subscribe(&menu, |this, _, _: &DismissEvent, cx| {
+            this.context_menu = None;
+            cx.notify();
+        }).detach();
+        self.context_menu = Some(menu);
+        cx.notify();
+    }
+
+    // The menu acts on the marked items, if any, like the keys do.
+    fn on_item_menu(&mut self, id: usize, position: Point<Pixels>, window: &mut Window, cx: &mut Context<Self>) {
+        if self.editing(window, cx) {
+            return;
+        }
+        let Some(name) = self.model.read(cx).visible_entry(id).map(|ent| ent.name.clone()) else {
+            return;
+        };
+        self.focus_handle.focus(window);
+        self.update_model(window, cx, move |model, cx| model.select(id, cx));
+        let model = self.model.read(cx);
+        let (nr_marked, _) = model.marked_size();
+        let title = match nr_marked {
+            0 => name,
+            1 => "1 marked item".to_string(),
+            _ => format!("{} marked items", nr_marked),
+        };
+        let is_trash = model.is_trash();
+        let mut menu = ContextMenu::new(position, Some(title.into()), self.focus_handle.clone())
+            .entry("Open", Open, window)
+            .entry("Open With\u{2026}", OpenWith, window)
+            .separator()
+            .entry("Copy", CopyOrCut { should_move: false }, window)
+            .entry("Cut", CopyOrCut { should_move: true }, window)
+            .entry("Copy as URI List", CopyUris, window)
+            .entry("Rename", Rename, window);
+        menu = if is_trash {
+            menu.entry("Restore", Restore, window).entry("Delete Permanently", Remove, window)
+        } else {
+            menu.entry("Move to Trash", Trash, window)
+        };
+        menu = menu.entry("Properties", Properties, window).separator();
+        for (index, action) in cx.global::<AppGlobal>().user_actions.iter().enumerate() {
+            menu = menu.entry(action.name.clone(), RunUserAction { index }, window);
+        }
+        self.show_context_menu(menu, cx);
+    }
+
+    fn on_space_menu(&mut self, event: &MouseDownEvent, window: &mut Window, cx: &mut Context<Self>) {
+        if self.editing(window, cx) {
+            return;
+        }
+        self.focus_handle.focus(window);
+        let show_hidden = self.model.read(cx).show_hidden;
+        let menu = ContextMenu::new(event.position, None, self.focus_handle.clone())
+            .entry("Paste", Paste, window)
+            .entry("New Folder", NewFolder, window)
+            .separator()
+            .entry("Refresh", Revert, window)
+            .entry(if show_hidden { "Hide Hidden Files" } else { "Show Hidden Files" }, ToggleHidden, window);
+        self.show_context_menu(menu, cx);
+    }
+
+    // From window to list content coordinates.
+    fn to_content(&self, pos: Point<Pixels>) -> Point<Pixels> {
+        let handle = &self.scroll_handle.0.borrow().base_handle;
+        pos - handle.bounds().origin - handle.offset()
+    }
+
+    // The position in visible of the item drawn at pos, in content
+    // coordinates. Margins around the items do not count.
+    fn item_at(&self, pos: Point<Pixels>, window: &mut Window, cx: &Context<Self>) -> Option<usize> {
+        let (width, height, margin) = (self.full_item_width(), self.full_item_height(), self.margin_size());
+        let (x, y) = (pos.x.0, pos.y.0);
+        if x < 0. || y < 0. {
+            return None;
+        }
+        let (col, line) = ((x / width) as usize, (y / height) as usize);
+        let (cell_x, cell_y) = (x - col as f32 * width, y - line as f32 * height);
+        let pos = line * self.items_per_line(window) + col;
+        let inside = cell_x >= margin && cell_x <= width - margin && cell_y >= margin && cell_y <= height - margin;
+        (inside && col < self.items_per_line(window) && pos < self.model.read(cx).visible.len()).then_some(pos)
+    }
+
+    // Positions in visible of the items the band touches.
+    fn band_positions(band: &RubberBand, width: f32, height: f32, margin: f32, per_line: usize) -> Vec<usize> {
+        let left = band.start.x.0.min(band.end.x.0).max(0.);
+        let right = band.start.x.0.max(band.end.x.0);
+        let top = band.start.y.0.min(band.end.y.0).max(0.);
+        let bottom = band.start.y.0.max(band.end.y.0);
+        if right < 0. || bottom < 0. || per_line == 0 {
+            return vec![];
+        }
+        let overlaps = |lo: f32, hi: f32, cell: usize, size: f32| {
+            lo <= (cell + 1) as f32 * size - margin && hi >= cell as f32 * size + margin
+        };
+        let cols = (left / width) as usize..=((right / width) as usize).min(per_line - 1);
+        let mut positions = vec![];
+        for line in (top / height) as usize..=(bottom / height) as usize {
+            if !overlaps(top, bottom, line, height) {
+                continue;
+            }
+            positions.extend(cols.clone()
+                .filter(|col| overlaps(left, right, *col, width))
+                .map(|col| line * per_line + col));
+        }
+        positions
+    }
+
+    fn update_band_marks(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(band) = &self.rubber_band else {
+            return;
+        };
+        let positions = Self::band_positions(band, self.full_item_width(), self.full_item_height(),
+                                             self.margin_size(), self.items_per_line(window));
+        self.model.update(cx, |model, _| model.mark_band(&band.base, positions.into_iter()));
+        cx.notify();
+    }
+
+    // Dragging from empty space starts a rubber band. With ctrl, it adds to
+    // the marks instead of replacing them.
+    fn on_band_start(&mut self, event: &MouseDownEvent, window: &mut Window, cx: &mut Context<Self>) {
+        let start = self.to_content(event.position);
+        if self.editing(window, cx) || self.item_at(start, window, cx).is_some() {
+            return;
+        }
+        self.focus_handle.focus(window);
+        let autoscroll = cx.spawn_in(window, async move |this, cx| {
+            loop {
+                cx.background_executor().timer(AUTOSCROLL_INTERVAL).await;
+                if this.update_in(cx, |this, window, cx| this.autoscroll_band(window, cx)).is_err() {
+                    break;
+                }
+            }
+        });
+        self.rubber_band = Some(RubberBand {
+            start,
+            end: start,
+            pointer: event.position,
+            base: self.model.read(cx).band_base(event.modifiers.secondary()),
+            _autoscroll: autoscroll,
+        });
+        self.update_band_marks(window, cx);
+    }
+
+    fn on_band_move(&mut self, event: &MouseMoveEvent, window: &mut Window, cx: &mut Context<Self>) {
+        if self.rubber_band.is_none() {
+            return;
+        }
+        // The button went up where we did not see it.
+        if event.pressed_button != Some(MouseButton::Left) {
+            self.on_band_end(cx);
+            return;
+        }
+        let end = self.to_content(event.position);
+        if let Some(band) = self.rubber_band.as_mut() {
+            band.pointer = event.position;
+            band.end = end;
+        }
+        self.update_band_marks(window, cx);
+    }
+
+    fn on_band_end(&mut self, cx: &mut Context<Self>) {
+        if self.rubber_band.take().is_some() {
+            cx.notify();
+        }
+    }
+
+    fn autoscroll_band(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(band) = &self.rubber_band else {
+            return;
+        };
+        let pointer = band.pointer;
+        let (bounds, offset, max_offset) = {
+            let handle = &self.scroll_handle.0.borrow().base_handle;
+            (handle.bounds(), handle.offset(), handle.max_offset())
+        };
+        let step = if pointer.y < bounds.top() + px(AUTOSCROLL_EDGE) {
+            AUTOSCROLL_STEP
+        } else if pointer.y > bounds.bottom() - px(AUTOSCROLL_EDGE) {
+            -AUTOSCROLL_STEP
+        } else {
+            return;
+        };
+        let y = (offset.y + px(step)).clamp(-max_offset.height, px(0.));
+        if y == offset.y {
+            return;
+        }
+        self.scroll_handle.0.borrow().base_handle.set_offset(point(offset.x, y));
+        let end = self.to_content(pointer);
+        if let Some(band) = self.rubber_band.as_mut() {
+            band.end = end;
+        }
+        self.update_band_marks(window, cx);
+    }
+
+    // The band as drawn over the list.
+    fn rubber_band_rect(&self) -> Option<Div> {
+        let band = self.rubber_band.as_ref()?;
+        let offset = self.scroll_handle.0.borrow().base_handle.offset();
+        let origin = point(band.start.x.min(band.end.x), band.start.y.min(band.end.y)) + offset;
+        let size = size((band.start.x - band.end.x).abs(), (band.start.y - band.end.y).abs());
+        Some(div()
+            .absolute()
+            .left(origin.x)
+            .top(origin.y)
+            .w(size.width)
+            .h(size.height)
+            .bg(rgba(0x0068d933))
+            .border_1()
+            .border_color(rgb(0x0068d9)))
+    }
+
     fn text_width(&self) -> f32 {
         self.icon_size * 1.5
     }
@@ -287,21 +821,15 @@ impl FileListView {
         self.icon_size = 64.;
     }
 
-    fn icon_image_source(&self, dir_ent: &DirEntry, mime: &str, window: &Window, cx: &App) -> ImageSource {
+    fn icon_image_source(&self, ent: &FileEntry, window: &Window, cx: &App) -> ImageSource {
         let app_global = cx.global::<AppGlobal>();
-        if dir_ent.file_type().map(|file_type| file_type.is_dir()).unwrap_or(false) {
+        if ent.is_dir() {
             app_global.match_directory_icon(self.icon_size as usize, window.scale_factor())
         } else {
-            app_global.match_file_icon(mime, self.icon_size as usize, window.scale_factor())
+            app_global.match_file_icon(&ent.mime, self.icon_size as usize, window.scale_factor())
         }
     }
 
-    fn mime_type(&self, dir_ent: &DirEntry, cx: &App) -> String {
-        let app_global = cx.global::<AppGlobal>();
-
-        app_global.match_mime_type(dir_ent.file_name().to_str().unwrap_or(""))
-    }
-
     fn clear_text_offset_cache(&mut self, window: &Window, cx: &App) {
         self.text_offset_cache_scale = window.scale_factor();
         self.text_offset_cache.clear();
@@ -311,15 +839,158 @@ impl FileListView {
 
     fn reset_status(&mut self, cx: &Context<Self>) {
         self.status_prompt = None;
-        self.status_text =
-            SharedString::from(format!("{} Items", self.model.read(cx).entries.len()));
+        let model = self.model.read(cx);
+        let nr_items = match model.filter.is_empty() {
+            true => model.entries.len().to_string(),
+            false => format!("{}/{}", model.visible.len(), model.entries.len()),
+        };
+        let note = if model.loading { Some("loading...") } else { self.status_note.as_deref() };
+        self.status_text = match note {
+            Some(note) => SharedString::from(format!("{} Items, {}", nr_items, note)),
+            None => SharedString::from(format!("{} Items", nr_items)),
+        };
+    }
+
+    fn on_open(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        let should_open_dir = self.model.read(cx).should_open_dir();
+        match should_open_dir {
+            Some(true) => {
+                let worker = self.model.update(cx, &DirModel::open_dir);
+                self.update_with_io_worker(window, cx, worker, &Self::io_worker_open_callback);
+            },
+            Some(false) => {
+                let worker = self.model.update(cx, &DirModel::open_file);
+                self.update_with_io_worker(window, cx, worker, |this, _window, cx, open_result| {
+                    this.model.update(cx, |_, cx| DirModel::after_open_file_result(open_result, cx));
+                });
+            },
+            None => {
+
+            },
+        }
+    }
+
+    // Click selects, ctrl-click toggles the mark, shift-click marks a range
+    // and double-click opens, like Enter.
+    fn on_item_click(&mut self, id: usize, event: &ClickEvent, window: &mut Window, cx: &mut Context<Self>) {
+        // A rename or mark prompt acts on the current item, so leave it be.
+        if self.editing(window, cx) {
+            return;
+        }
+        self.focus_handle.focus(window);
+        let modifiers = event.modifiers();
+        if modifiers.shift {
+            self.update_model(window, cx, move |model, cx| model.mark_range(id, cx));
+        } else if modifiers.secondary() {
+            self.update_model(window, cx, move |model, cx| model.toggle_mark_at(id, cx));
+        } else {
+            self.update_model(window, cx, move |model, cx| model.select(id, cx));
+            if event.click_count() >= 2 && self.model.read(cx).current == Some(id) {
+                self.on_open(window, cx);
+            }
+        }
+    }
+
+    fn on_item_middle_click(&mut self, id: usize, _window: &mut Window, cx: &mut Context<Self>) {
+        let Some(ent) = self.model.read(cx).visible_entry(id) else {
+            return;
+        };
+        if !ent.is_dir() {
+            self.status_note = Some(format!("{} is not a directory", ent.name));
+            self.reset_status(cx);
+            cx.notify();
+            return;
+        }
+        let dir_path = ent.path.clone();
+        cx.spawn(async |_, cx: &mut AsyncApp| {
+            AppGlobal::new_main_window(dir_path, cx);
+        }).detach();
+    }
+
+    // GPUI cannot hand a drag over to other applications, so this puts the
+    // items on the clipboard as a text/uri-list for pasting into them.
+    fn on_copy_uris(&mut self, cx: &mut Context<Self>) {
+        let paths = self.model.read(cx).operate_paths();
+        if paths.is_empty() {
+            return;
+        }
+        cx.write_to_clipboard(ClipboardItem::new_string(entry::uri_list(&paths)));
+        self.status_note = Some(format!("Copied {} item{} as URIs", paths.len(), if paths.len() == 1 { "" } else { "s" }));
+        self.reset_status(cx);
+        cx.notify();
+    }
+
+    fn on_drop_entries(&mut self, dragged: &DraggedEntries, target: Option<PathBuf>, window: &mut Window, cx: &mut Context<Self>) {
+        let Some(paths) = dragged.paths.get() else {
+            return;
+        };
+        self.on_drop_paths(paths.clone(), target, window, cx);
+    }
+
+    // Drops go into the folder dropped on, or else the directory shown.
+    // Shift moves and ctrl links, as they were held when letting go.
+    fn on_drop_paths(&mut self, mut paths: Vec<PathBuf>, target: Option<PathBuf>, window: &mut Window, cx: &mut Context<Self>) {
+        let dir = target.unwrap_or_else(|| self.model.read(cx).dir_path.clone());
+        // Dropping items where they already are does nothing.
+        paths.retain(|path| path.parent() != Some(dir.as_path()));
+        if paths.is_empty() {
+            return;
+        }
+        let modifiers = window.modifiers();
+        let action = if modifiers.shift {
+            DropAction::Move
+        } else if modifiers.control {
+            DropAction::Link
+        } else {
+            DropAction::Copy
+        };
+        let worker = self.model.update(cx, |model, cx| model.drop_paths(paths, dir, action, cx));
+        self.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+    }
+
+    // The right side of the status bar: what is marked, the current item and
+    // the free space. All of it was read off the UI thread.
+    fn status_details(&self, cx: &Context<Self>) -> String {
+        let model = self.model.read(cx);
+        let mut parts = vec![];
+        let (nr_marked, marked_size) = model.marked_size();
+        if nr_marked > 0 {
+            parts.push(format!("{} marked, {}", nr_marked, entry::format_size(marked_size)));
+        }
+        if let Some(ent) = model.current.map(|cur| &model.entries[cur]) {
+            let mut detail = entry::format_mode(ent.file_type, &ent.permissions);
+            if !ent.is_dir() {
+                detail = format!("{} {}", detail, entry::format_size(ent.size));
+            }
+            if let Some(mtime) = ent.mtime {
+                detail = format!("{} {}", detail, entry::format_mtime(mtime));
+            }
+            if let Some(target) = &ent.symlink_target {
+                detail = format!("{} -> {}", detail, entry::escape_name(target.as_os_str()));
+            }
+            parts.push(detail);
+        }
+        if let Some((free, total)) = model.fs_space {
+            parts.push(format!("{} free of {}", entry::format_size(free), entry::format_size(total)));
+        }
+        parts.join("  |  ")
     }
 
     pub fn on_navigate(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.show_location(window, cx);
+        self.line_edit.update(cx, |_, cx| { cx.emit(DismissEvent); });
+    }
+
+    // Puts the directory in the title, and forgets what was noted about the
+    // last one.
+    fn show_location(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.status_note = None;
         self.clear_text_offset_cache(window, cx);
-        let path = self.model.read(cx).dir_path.to_str().unwrap().to_owned();
+        let mut path = entry::escape_name(self.model.read(cx).dir_path.as_os_str());
+        if let Some(pattern) = &self.model.read(cx).find {
+            path = format!("{} ({})", path, pattern);
+        }
         window.set_window_title(&path);
-        self.line_edit.update(cx, |_, cx| { cx.emit(DismissEvent); });
     }
 
     pub fn popup_line_edit(&mut self, window: &mut Window, cx: &mut Context<Self>, prompt: Option<StatusPrompt>, existing_text: Option<String>) {
@@ -336,15 +1007,38 @@ impl FileListView {
     }
 
     fn on_search(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.search_step(window, cx, true);
+    }
+
+    fn on_search_backward(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.search_step(window, cx, false);
+    }
+
+    fn search_step(&mut self, window: &mut Window, cx: &mut Context<Self>, forward: bool) {
+        let isearch = self.isearch_active(window, cx);
         if self.model.read(cx).start_with.is_empty() {
-            self.popup_line_edit(window, cx, Some(StatusPrompt::Search), None);
-        } else {
-            self.model.update(cx, &DirModel::search_next);
+            if !isearch {
+                self.model.update(cx, &DirModel::search_start);
+                self.popup_line_edit(window, cx, Some(StatusPrompt::Search), None);
+            }
+            return;
+        }
+        let found = self.model.update(cx, |model, cx| if forward { model.search_next(cx) } else { model.search_prev(cx) });
+        if isearch {
+            self.show_search_result(found, cx);
         }
     }
 
+    fn on_search_undo(&mut self, _window: &mut Window, cx: &mut Context<Self>) {
+        let Some((query, found)) = self.model.update(cx, &DirModel::search_undo) else {
+            return;
+        };
+        self.line_edit.update(cx, |edit, cx| edit.set_text(query, cx));
+        self.show_search_result(found, cx);
+    }
+
     fn text_offset_for_item(&mut self, window: &Window, cx: &App, idx: usize) -> f32 {
-        if self.text_offset_cache_scale != window.scale_factor() {
+        if self.text_offset_cache_scale != window.scale_factor() || idx >= self.text_offset_cache.len() {
             self.clear_text_offset_cache(window, cx);
         }
         if let Some(text_offset) = self.text_offset_cache[idx] {
@@ -356,10 +1050,7 @@ impl FileListView {
         let font_size = px(self.font_size());
         let text_system = window.text_system();
         let text_style = window.text_style();
-        let text = self.model.read(cx).entries[idx]
-            .file_name()
-            .into_string()
-            .unwrap_or(FILENAME_FALLBACK.to_string());
+        let text = self.model.read(cx).label(idx).unwrap_or(FILENAME_FALLBACK.to_string());
 
         let runs = vec![TextRun {
             len: text.len(),
@@ -397,6 +1088,12 @@ impl FileListView {
         (window.bounds().size.width.to_f64() as f32 / self.full_item_width()) as usize
     }
 
+    // How many rows fit in the list, at least one.
+    fn lines_per_page(&self) -> usize {
+        let list_height = self.scroll_handle.0.borrow().base_handle.bounds().size.height.0;
+        ((list_height / self.full_item_height()) as usize).max(1)
+    }
+
     pub fn update_model<Func>(&mut self, window: &mut Window, cx: &mut Context<Self>, func: Func)
     where
         Func:
@@ -419,8 +1116,11 @@ impl FileListView {
         self.model.update(cx, func.clone());
         view_func.clone()(self, window, cx);
 
-        self.scroll_handle
-            .scroll_to_item(self.model.read(cx).current.unwrap_or(0) / self.items_per_line(window), ScrollStrategy::Top);
+        // Follows the cursor: a row above the view scrolls in at the top,
+        // one below at the bottom. Nothing moves if the row is in view.
+        let line = self.model.read(cx).current_pos().unwrap_or(0) / self.items_per_line(window).max(1);
+        let strategy = if line <= self.scroll_range.start { ScrollStrategy::Top } else { ScrollStrategy::Bottom };
+        self.scroll_handle.scroll_to_item(line, strategy);
 
         cx.notify();
     }
@@ -433,12 +1133,33 @@ impl FileListView {
     }
 
     fn io_worker_refresh_callback(&mut self, window: &mut Window, cx: &mut Context<Self>, open_result: OpenDirResult) {
-        self.model.update(cx, |model, _| model.refresh_with_result(open_result));
+        self.model.update(cx, |model, cx| model.refresh_with_result(open_result, cx));
         self.on_navigate(window, cx);
     }
 
+    fn io_worker_revert_callback(&mut self, window: &mut Window, cx: &mut Context<Self>, open_result: OpenDirResult) {
+        let stats = self.model.update(cx, |model, cx| model.revert_with_result(open_result, cx));
+        self.clear_text_offset_cache(window, cx);
+        self.status_note = Some(format!("+{} -{}", stats.appeared, stats.disappeared));
+        self.reset_status(cx);
+        cx.notify();
+    }
+
+    fn change_sort(&mut self, window: &mut Window, cx: &mut Context<Self>, change: impl FnOnce(&mut SortOrder)) {
+        let mut sort = self.model.read(cx).sort;
+        change(&mut sort);
+        let worker = self.model.update(cx, |model, cx| model.set_sort(sort, cx));
+        self.update_with_io_worker(window, cx, worker, move |this, window, cx, open_result| {
+            this.model.update(cx, |model, cx| model.sort_with_result(sort, open_result, cx));
+            this.clear_text_offset_cache(window, cx);
+            this.status_note = Some(sort.describe());
+            this.reset_status(cx);
+            cx.notify();
+        });
+    }
+
     fn io_worker_open_callback(&mut self, window: &mut Window, cx: &mut Context<Self>, open_result: OpenDirResult) {
-        self.model.update(cx, |model, _| model.open_with_result(open_result));
+        self.model.update(cx, |model, cx| model.open_with_result(open_result, cx));
         self.on_navigate(window, cx);
     }
 
@@ -502,7 +1223,7 @@ impl FileListView {
 impl Render for FileListView {
     fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
         let per_line = self.items_per_line(window);
-        let nr_items = self.model.read(cx).entries.len();
+        let nr_items = self.model.read(cx).visible.len();
         let nr_line = (nr_items + per_line - 1) / per_line;
 
         // println!("nr_items {} per-line {} nr_line {} content height {}",
@@ -517,12 +1238,27 @@ impl Render for FileListView {
         // println!("off {} height {}", off.y.0, cx.bounds().size.height.0);
 
         let mut status_children = vec![div()
-            .w(px(128.))
+            .min_w(px(128.))
+            .whitespace_nowrap()
             .text_size(px(12.))
             .child(self.status_text.clone())];
         if let Some(prompt) = &self.status_prompt {
             status_children.insert(0, div().flex_auto().child(self.line_edit.clone()));
-            status_children.insert(0, div().text_size(px(12.)).child(prompt.to_str()));
+            let prompt = match prompt {
+                StatusPrompt::Search => self.model.read(cx).search_mode.prompt(),
+                StatusPrompt::Find if self.model.read(cx).search_mode == SearchMode::Regex => "Find name regex: ",
+                _ => prompt.to_str(),
+            };
+            status_children.insert(0, div().text_size(px(12.)).child(prompt));
+        } else {
+            status_children.push(div()
+                .flex_auto()
+                .overflow_hidden()
+                .whitespace_nowrap()
+                .text_right()
+                .px_2()
+                .text_size(px(12.))
+                .child(self.status_details(cx)));
         }
 
         div()
@@ -548,15 +1284,17 @@ impl Render for FileListView {
                                     let mut line = Vec::new();
                                     let last_in_line =
                                         std::cmp::min((lidx + 1) * per_line, nr_items);
-                                    for id in lidx * per_line..last_in_line {
-                                        let dir_ent = &this.model.read(cx).entries[id];
-                                        let mime = this.mime_type(dir_ent, cx);
+                                    for pos in lidx * per_line..last_in_line {
+                                        let model = this.model.read(cx);
+                                        let Some((id, ent)) = model.visible.get(pos)
+                                            .and_then(|id| Some((*id, model.entries.get(*id)?))) else {
+                                            break;
+                                        };
 
                                         line.push(DirEntryView::new(
                                             id,
-                                            this.icon_image_source(dir_ent, &mime, window, cx),
+                                            this.icon_image_source(ent, window, cx),
                                             cx.entity().clone(),
-                                            mime,
                                             this.model.clone(),
                                             this.text_offset_for_item(window, cx, id),
                                         ));
@@ -579,6 +1317,16 @@ impl Render for FileListView {
                                 .bg(rgb(0x59cdff)),
                         ),
                     )
+                    .children(self.rubber_band_rect())
+                    .on_mouse_down(MouseButton::Left, cx.listener(Self::on_band_start))
+                    .on_mouse_down(MouseButton::Right, cx.listener(Self::on_space_menu))
+                    .on_drop(cx.listener(|this, dragged: &DraggedEntries, window, cx| {
+                        this.on_drop_entries(dragged, None, window, cx);
+                    }))
+                    .on_drop(cx.listener(|this, paths: &ExternalPaths, window, cx| {
+                        this.on_drop_paths(paths.paths().to_vec(), None, window, cx);
+                    }))
+                    .relative()
                     .size_full(),
             )
             .child(
@@ -588,46 +1336,170 @@ impl Render for FileListView {
                     .bg(rgb(0xefefef))
                     .children(status_children),
             )
+            .children(self.context_menu.clone())
             .child(self.dialog.clone())
+            .on_mouse_move(cx.listener(Self::on_band_move))
+            .on_mouse_up(MouseButton::Left, cx.listener(|this, _, _, cx| this.on_band_end(cx)))
+            .on_mouse_up_out(MouseButton::Left, cx.listener(|this, _, _, cx| this.on_band_end(cx)))
             .on_action(cx.listener(|this: &mut Self, action: &MoveAction, window, cx| {
                 match action {
                     MoveAction::Next => { this.update_model(window, cx, &DirModel::move_next); },
                     MoveAction::Prev => { this.update_model(window, cx, &DirModel::move_prev); },
                     MoveAction::Home => { this.update_model(window, cx, &DirModel::move_home); },
                     MoveAction::End => { this.update_model(window, cx, &DirModel::move_end); },
+                    MoveAction::Up | MoveAction::Down | MoveAction::PageUp | MoveAction::PageDown => {
+                        let per_line = this.items_per_line(window);
+                        let lines = match action {
+                            MoveAction::Up => -1,
+                            MoveAction::Down => 1,
+                            MoveAction::PageUp => -(this.lines_per_page() as isize),
+                            _ => this.lines_per_page() as isize,
+                        };
+                        this.update_model(window, cx, move |model, cx| model.move_lines(lines, per_line, cx));
+                    },
+                    MoveAction::Left | MoveAction::Right => {
+                        let per_line = this.items_per_line(window);
+                        let columns = if *action == MoveAction::Left { -1 } else { 1 };
+                        this.update_model(window, cx, move |model, cx| model.move_columns(columns, per_line, cx));
+                    },
                 }
             }))
             .on_action(cx.listener(|this: &mut Self, _: &ToggleMark, window, cx| {
                 this.update_model(window, cx, &DirModel::toggle_mark);
             }))
+            .on_action(cx.listener(|this: &mut Self, _: &Unmark, window, cx| {
+                this.update_model(window, cx, &DirModel::unmark);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &UnmarkAll, _window, cx| {
+                this.model.update(cx, &DirModel::unmark_all);
+                cx.notify();
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &InvertMarks, _window, cx| {
+                this.model.update(cx, &DirModel::invert_marks);
+                cx.notify();
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &MarkDirs, _window, cx| {
+                let marked = this.model.update(cx, &DirModel::mark_dirs);
+                this.note_count(marked, "marked", cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &MarkSymlinks, _window, cx| {
+                let marked = this.model.update(cx, &DirModel::mark_symlinks);
+                this.note_count(marked, "marked", cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &MarkExecutables, _window, cx| {
+                let marked = this.model.update(cx, &DirModel::mark_executables);
+                this.note_count(marked, "marked", cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &MarkRegex, window, cx| {
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::MarkRegex), None);
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &MarkExtension, window, cx| {
+                // Defaults to the extension of the current item, like Dired.
+                let existing_text = this.model.read(cx).current_extension();
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::MarkExtension), existing_text.clone());
+                });
+            }))
             .on_action(cx.listener(|this: &mut Self, _: &ToggleHidden, window, cx| {
-                this.update_model_view(window, cx, &DirModel::toggle_hidden, &FileListView::on_navigate);
+                let show_hidden = !this.model.read(cx).show_hidden;
+                let worker = this.model.update(cx, &DirModel::toggle_hidden);
+                this.update_with_io_worker(window, cx, worker, move |this, window, cx, open_result| {
+                    this.model.update(cx, |model, cx| model.hidden_with_result(show_hidden, open_result, cx));
+                    this.on_navigate(window, cx);
+                });
             }))
             .on_action(cx.listener(|this: &mut Self, _: &Open, window, cx| {
-                let should_open_dir = this.model.read(cx).should_open_dir();
-                match should_open_dir {
-                    Some(true) => {
-                        let worker = this.model.update(cx, &DirModel::open_dir);
-                        this.update_with_io_worker(window, cx, worker, &Self::io_worker_open_callback);
-                    },
-                    Some(false) => {
-                        let worker = this.model.update(cx, &DirModel::open_file);
-                        this.update_with_io_worker(window, cx, worker, |this, _window, cx, open_result| {
-                            this.model.update(cx, |_, cx| DirModel::after_open_file_result(open_result, cx));
-                        });
-                    },
-                    None => {
-
-                    },
+                this.on_open(window, cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &OpenWith, window, cx| {
+                // Directories too, with the applications for inode/directory.
+                if this.model.read(cx).current.is_none() {
+                    return;
                 }
+                let worker = this.model.update(cx, &DirModel::open_file);
+                this.update_with_io_worker(window, cx, worker, |this, _window, cx, open_result| {
+                    this.model.update(cx, |_, cx| DirModel::after_open_file_result(open_result, cx));
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Properties, window, cx| {
+                let Some(properties) = this.model.read(cx).properties() else {
+                    return;
+                };
+                this.dialog.update(cx, |dialog, cx| dialog.show_just_error(properties.into(), window, cx));
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &NewFolder, window, cx| {
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::NewFolder), None);
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, action: &RunUserAction, window, cx| {
+                let index = action.index;
+                let worker = this.model.update(cx, |model, cx| model.run_user_action(index, cx));
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
             }))
             .on_action(cx.listener(|this: &mut Self, action: &CopyOrCut, _window, cx| {
                 this.model.update(cx, |model, cx| model.copy_or_move(cx, action.should_move));
             }))
+            .on_action(cx.listener(|this: &mut Self, _: &CopyUris, _window, cx| {
+                this.on_copy_uris(cx);
+            }))
             .on_action(cx.listener(|this: &mut Self, _: &Paste, window, cx| {
                 let worker = this.model.update(cx, &DirModel::paste);
                 this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
             }))
+            .on_action(cx.listener(|this: &mut Self, _: &Trash, window, cx| {
+                let worker = this.model.update(cx, &DirModel::trash);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Flag, window, cx| {
+                this.update_model(window, cx, &DirModel::flag);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &FlagBackups, _window, cx| {
+                let flagged = this.model.update(cx, &DirModel::flag_backups);
+                this.note_count(flagged, "flagged", cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &FlagAutosaves, _window, cx| {
+                let flagged = this.model.update(cx, &DirModel::flag_autosaves);
+                this.note_count(flagged, "flagged", cx);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &ExecuteFlagged, window, cx| {
+                let worker = this.model.update(cx, &DirModel::execute_flagged);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &OpenTrash, window, cx| {
+                let worker = this.model.update(cx, &DirModel::open_trash);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_open_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Restore, window, cx| {
+                let worker = this.model.update(cx, &DirModel::restore);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &EmptyTrash, window, cx| {
+                let worker = this.model.update(cx, &DirModel::empty_trash);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Revert, window, cx| {
+                let worker = this.model.update(cx, &DirModel::revert);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_revert_callback);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &CycleSort, window, cx| {
+                this.change_sort(window, cx, |sort| sort.mode = sort.mode.next());
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &ReverseSort, window, cx| {
+                this.change_sort(window, cx, |sort| sort.reverse = !sort.reverse);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &ToggleDirsFirst, window, cx| {
+                this.change_sort(window, cx, |sort| sort.dirs_first = !sort.dirs_first);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &ToggleNaturalSort, window, cx| {
+                this.change_sort(window, cx, |sort| sort.natural = !sort.natural);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Undo, window, cx| {
+                let worker = this.model.update(cx, &DirModel::undo);
+                this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
+            }))
             .on_action(cx.listener(move |this: &mut Self, _: &Remove, window, cx| {
                 let worker = this.model.update(cx, &DirModel::delete);
                 this.update_with_io_worker(window, cx, worker, &Self::io_worker_refresh_callback);
@@ -640,7 +1512,7 @@ impl Render for FileListView {
                 if this.model.read(cx).start_with.is_empty() {
                     let worker = this.model.update(cx, &DirModel::back);
                     this.update_with_io_worker(window, cx, worker, |this, window, cx, open_result| {
-                        this.model.update(cx, |model, _| model.back_with_result(open_result));
+                        this.model.update(cx, |model, cx| model.back_with_result(open_result, cx));
                         this.on_navigate(window, cx);
                     });
                 } else {
@@ -655,17 +1527,51 @@ impl Render for FileListView {
             .on_action(cx.listener(|this: &mut Self, _: &Search, window, cx| {
                 this.update_view(window, cx, &FileListView::on_search);
             }))
+            .on_action(cx.listener(|this: &mut Self, _: &SearchBackward, window, cx| {
+                this.update_view(window, cx, &FileListView::on_search_backward);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &SearchUndo, window, cx| {
+                this.update_view(window, cx, &FileListView::on_search_undo);
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &Filter, window, cx| {
+                let existing_text = this.model.read(cx).filter.clone();
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::Filter), Some(existing_text.clone()));
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &FindName, window, cx| {
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::Find), None);
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &FindGrep, window, cx| {
+                this.update_view(window, cx, |this, window, cx| {
+                    this.popup_line_edit(window, cx, Some(StatusPrompt::Grep), None);
+                });
+            }))
+            .on_action(cx.listener(|this: &mut Self, _: &CycleSearchMode, window, cx| {
+                this.update_view(window, cx, &FileListView::on_cycle_search_mode);
+            }))
             .on_action(cx.listener(|this: &mut Self, _: &Rename, window, cx| {
                 let Some(cur) = this.model.read(cx).current else {
                     return;
                 };
-                let existing_text = this.model.read(cx).entries[cur].file_name().to_string_lossy().to_string();
+                let existing_text = this.model.read(cx).entries[cur].name.clone();
 
                 this.update_view(window, cx, |this, window, cx| {
                     this.popup_line_edit(window, cx, Some(StatusPrompt::Rename), Some(existing_text.clone()));
                 });
             }))
             .on_action(cx.listener(|this: &mut Self, _: &Escape, _window, cx| {
+                if this.context_menu.take().is_some() {
+                    cx.notify();
+                    return;
+                }
+                if this.model.update(cx, &DirModel::stop_loading) {
+                    this.status_note = Some("stopped loading".to_string());
+                } else if !this.model.read(cx).filter.is_empty() {
+                    this.model.update(cx, &DirModel::clear_filter);
+                }
                 // TODO: clear other UI modes too.
                 this.line_edit.update(cx, |_, cx| cx.emit(DismissEvent));
             }))
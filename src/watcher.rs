@@ -0,0 +1,82 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+use smol::channel::Receiver;
+
+pub enum DirEvent {
+    // Something happened to this name: it was created, deleted, renamed,
+    // written or had its attributes changed.
+    Touched(OsString),
+    // The kernel dropped events, re-read the whole directory.
+    Overflow,
+    // The directory itself was deleted or moved away.
+    Gone,
+}
+
+#[cfg(target_os = "linux")]
+pub struct DirWatcher {
+    watches: inotify::Watches,
+    wd: inotify::WatchDescriptor,
+}
+
+#[cfg(target_os = "linux")]
+impl DirWatcher {
+    pub fn new(path: &Path) -> std::io::Result<(Self, Receiver<DirEvent>)> {
+        use inotify::{EventMask, Inotify, WatchMask};
+
+        let mut inotify = Inotify::init()?;
+        let mut watches = inotify.watches();
+        // CLOSE_WRITE and ATTRIB keep the cached sizes, times and
+        // permissions up to date.
+        let wd = watches.add(path, WatchMask::CREATE | WatchMask::DELETE | WatchMask::MOVED_FROM
+                             | WatchMask::MOVED_TO | WatchMask::CLOSE_WRITE | WatchMask::ATTRIB
+                             | WatchMask::DELETE_SELF | WatchMask::MOVE_SELF)?;
+        let (send, recv) = smol::channel::unbounded();
+
+        // Reads block, so they get their own thread. The thread exits once the
+        // watch is removed (IN_IGNORED) or nobody listens anymore.
+        std::thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            loop {
+                let Ok(events) = inotify.read_events_blocking(&mut buffer) else {
+                    return;
+                };
+                for event in events {
+                    let ev = if event.mask.contains(EventMask::IGNORED) {
+                        return;
+                    } else if event.mask.contains(EventMask::Q_OVERFLOW) {
+                        DirEvent::Overflow
+                    } else if event.mask.intersects(EventMask::DELETE_SELF | EventMask::MOVE_SELF) {
+                        DirEvent::Gone
+                    } else if let Some(name) = event.name {
+                        DirEvent::Touched(name.to_os_string())
+                    } else {
+                        continue;
+                    };
+                    if send.send_blocking(ev).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok((Self { watches, wd }, recv))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DirWatcher {
+    fn drop(&mut self) {
+        let _ = self.watches.remove(self.wd.clone());
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct DirWatcher;
+
+#[cfg(not(target_os = "linux"))]
+impl DirWatcher {
+    pub fn new(_path: &Path) -> std::io::Result<(Self, Receiver<DirEvent>)> {
+        Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+    }
+}